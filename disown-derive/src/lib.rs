@@ -0,0 +1,218 @@
+//! Derive macros for the [`disown`](https://docs.rs/disown) crate.
+//!
+//! These are re-exported by `disown` when its `derive` feature is enabled, and
+//! shouldn't need to be depended upon directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, Type};
+
+/// Derive `disown::deep::DeepDisown` for a struct or enum.
+///
+/// Every field is torn down with `DeepDisown`, unless it's marked with
+/// `#[deep_disown(shallow)]`, in which case it's dropped normally.
+#[proc_macro_derive(DeepDisown, attributes(deep_disown))]
+pub fn derive_deep_disown(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    deep_disown(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn deep_disown(mut input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let mut deep_types: Vec<Type> = Vec::new();
+
+    let arms = match &input.data {
+        Data::Struct(data) => {
+            let (pattern, body) = destructure(&data.fields, &mut deep_types)?;
+            vec![quote!(#name #pattern => { #body })]
+        }
+        Data::Enum(data) => data
+            .variants
+            .iter()
+            .map(|v| {
+                let variant = &v.ident;
+                let (pattern, body) = destructure(&v.fields, &mut deep_types)?;
+                Ok(quote!(#name::#variant #pattern => { #body }))
+            })
+            .collect::<syn::Result<Vec<_>>>()?,
+        Data::Union(_) => {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "DeepDisown can't be derived for unions",
+            ))
+        }
+    };
+
+    for param in input.generics.type_params_mut() {
+        param.bounds.push(parse_quote!(::disown::deep::DeepDisown));
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let shallow = if deep_types.is_empty() {
+        quote!(true)
+    } else {
+        quote!(#(<#deep_types as ::disown::deep::DeepDisown>::SHALLOW)&&*)
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::disown::deep::DeepDisown for #name #ty_generics #where_clause {
+            const SHALLOW: bool = #shallow;
+
+            #[allow(unused_variables)]
+            fn push_children<'__disown>(
+                self,
+                work: &mut ::disown::deep::WorkList<'__disown>,
+            ) where
+                Self: '__disown,
+            {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+    })
+}
+
+/// A pattern binding every field, and the statements that tear them down.
+fn destructure(
+    fields: &Fields,
+    deep_types: &mut Vec<Type>,
+) -> syn::Result<(TokenStream2, TokenStream2)> {
+    let mut bindings: Vec<Ident> = Vec::new();
+    let mut body = TokenStream2::new();
+
+    for (i, field) in fields.iter().enumerate() {
+        let binding = match &field.ident {
+            Some(ident) => ident.clone(),
+            None => format_ident!("__field{}", i),
+        };
+
+        if is_shallow(field)? {
+            body.extend(quote!(::core::mem::drop(#binding);));
+        } else {
+            body.extend(quote!(::disown::deep::DeepDisown::push_children(#binding, work);));
+            deep_types.push(field.ty.clone());
+        }
+
+        bindings.push(binding);
+    }
+
+    let pattern = match fields {
+        Fields::Named(_) => quote!({ #(#bindings),* }),
+        Fields::Unnamed(_) => quote!(( #(#bindings),* )),
+        Fields::Unit => quote!(),
+    };
+
+    Ok((pattern, body))
+}
+
+fn is_shallow(field: &syn::Field) -> syn::Result<bool> {
+    has_flag(field, "deep_disown", "shallow")
+}
+
+/// Derive `disown::zeroize::DisownZeroized` for a struct or enum.
+///
+/// Every field is wiped, unless it's marked with `#[disown_zeroized(skip)]`.
+#[proc_macro_derive(DisownZeroized, attributes(disown_zeroized))]
+pub fn derive_disown_zeroized(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    disown_zeroized(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn disown_zeroized(mut input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+
+    let arms = match &input.data {
+        Data::Struct(data) => {
+            let (pattern, body) = wipe(&data.fields)?;
+            vec![quote!(#name #pattern => { #body })]
+        }
+        Data::Enum(data) => data
+            .variants
+            .iter()
+            .map(|v| {
+                let variant = &v.ident;
+                let (pattern, body) = wipe(&v.fields)?;
+                Ok(quote!(#name::#variant #pattern => { #body }))
+            })
+            .collect::<syn::Result<Vec<_>>>()?,
+        Data::Union(_) => {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "DisownZeroized can't be derived for unions",
+            ))
+        }
+    };
+
+    for param in input.generics.type_params_mut() {
+        param
+            .bounds
+            .push(parse_quote!(::disown::zeroize::DisownZeroized));
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::disown::zeroize::DisownZeroized for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn wipe(&mut self) {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+    })
+}
+
+/// A pattern binding every field by reference, and the statements that wipe
+/// them.
+fn wipe(fields: &Fields) -> syn::Result<(TokenStream2, TokenStream2)> {
+    let mut bindings: Vec<Ident> = Vec::new();
+    let mut body = TokenStream2::new();
+
+    for (i, field) in fields.iter().enumerate() {
+        let binding = match &field.ident {
+            Some(ident) => ident.clone(),
+            None => format_ident!("__field{}", i),
+        };
+
+        if !has_flag(field, "disown_zeroized", "skip")? {
+            body.extend(quote!(::disown::zeroize::DisownZeroized::wipe(#binding);));
+        }
+
+        bindings.push(binding);
+    }
+
+    let pattern = match fields {
+        Fields::Named(_) => quote!({ #(#bindings),* }),
+        Fields::Unnamed(_) => quote!(( #(#bindings),* )),
+        Fields::Unit => quote!(),
+    };
+
+    Ok((pattern, body))
+}
+
+/// Is the field marked with `#[attr(flag)]`?
+fn has_flag(field: &syn::Field, attr: &str, flag: &str) -> syn::Result<bool> {
+    let mut found = false;
+
+    for a in field.attrs.iter().filter(|a| a.path().is_ident(attr)) {
+        a.parse_nested_meta(|meta| {
+            if meta.path.is_ident(flag) {
+                found = true;
+                Ok(())
+            } else {
+                Err(meta.error(format!("expected `{}`", flag)))
+            }
+        })?;
+    }
+
+    Ok(found)
+}
@@ -0,0 +1,141 @@
+//! Fallible disposal, for resources that need async cleanup.
+//!
+//! An async file or socket can't be flushed or shut down from within `Drop`.
+//! The [`AsyncDisown`] trait is the async counterpart of [`Close`](crate::Close):
+//! it releases a resource on purpose, awaiting whatever cleanup it needs, and
+//! hands back whatever went wrong.
+//!
+//! [`PlainDrop`] types are covered as well, and any other value can be wrapped
+//! in [`Blocking`] to fall back to a plain drop, so that async code has one
+//! uniform way to release everything it holds.
+//!
+//! ```
+//! use disown::async_close::{AsyncDisown, Blocking};
+//!
+//! # fn block_on<F: std::future::Future>(f: F) -> F::Output {
+//! #     let mut f = std::pin::pin!(f);
+//! #     let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+//! #     loop {
+//! #         if let std::task::Poll::Ready(v) = f.as_mut().poll(&mut cx) {
+//! #             return v;
+//! #         }
+//! #     }
+//! # }
+//! struct Session;
+//!
+//! block_on(async {
+//!     vec![String::from("cached")].disown_async().await.unwrap();
+//!     Blocking(Session).disown_async().await.unwrap();
+//! });
+//! ```
+//!
+//! With the `tokio-fs`, `tokio-io` and `tokio-net` features, tokio's `File`,
+//! `BufWriter` and `TcpStream` are covered too.
+
+use std::future::Future;
+use std::io;
+
+use crate::close::PlainDrop;
+
+/// Consume ownership asynchronously, reporting any error that a plain drop
+/// would swallow.
+pub trait AsyncDisown: Sized {
+    /// What can go wrong while releasing `self`.
+    type Error;
+
+    /// Release `self`, returning the first error encountered while doing so.
+    fn disown_async(self) -> impl Future<Output = Result<(), Self::Error>> + Send;
+}
+
+impl<T: PlainDrop + Send> AsyncDisown for T {
+    type Error = io::Error;
+
+    async fn disown_async(self) -> io::Result<()> {
+        drop(self);
+        Ok(())
+    }
+}
+
+/// Releases any value with a plain, blocking drop.
+///
+/// For values with no async cleanup to do, or none that is worth awaiting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Blocking<T>(pub T);
+
+impl<T> Blocking<T> {
+    /// Unwrap the value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Send> AsyncDisown for Blocking<T> {
+    type Error = io::Error;
+
+    async fn disown_async(self) -> io::Result<()> {
+        drop(self.0);
+        Ok(())
+    }
+}
+
+/// Completes any in-flight writes, then flushes all data and metadata to disk
+/// with `sync_all`.
+#[cfg(feature = "tokio-fs")]
+impl AsyncDisown for tokio::fs::File {
+    type Error = io::Error;
+
+    async fn disown_async(self) -> io::Result<()> {
+        self.sync_all().await
+    }
+}
+
+/// Flushes the buffer, then releases the underlying writer.
+///
+/// ```
+/// use disown::async_close::AsyncDisown;
+/// use tokio::io::{AsyncWriteExt, BufWriter};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+///
+/// runtime.block_on(async {
+///     let mut out = BufWriter::new(Vec::new());
+///     out.write_all(b"Hello!\n").await?;
+///     out.disown_async().await
+/// })
+/// # }
+/// ```
+#[cfg(feature = "tokio-io")]
+impl<W> AsyncDisown for tokio::io::BufWriter<W>
+where
+    W: tokio::io::AsyncWrite + AsyncDisown<Error = io::Error> + Unpin + Send,
+{
+    type Error = io::Error;
+
+    async fn disown_async(mut self) -> io::Result<()> {
+        tokio::io::AsyncWriteExt::flush(&mut self).await?;
+        self.into_inner().disown_async().await
+    }
+}
+
+/// Shuts down the writing half of the connection, so that the peer sees a
+/// clean end of stream. A peer that has already gone away is not an error.
+#[cfg(feature = "tokio-net")]
+impl AsyncDisown for tokio::net::TcpStream {
+    type Error = io::Error;
+
+    async fn disown_async(mut self) -> io::Result<()> {
+        crate::close::shutdown(tokio::io::AsyncWriteExt::shutdown(&mut self).await)
+    }
+}
+
+/// Shuts down the writing half of the connection, so that the peer sees a
+/// clean end of stream. A peer that has already gone away is not an error.
+#[cfg(all(unix, feature = "tokio-net"))]
+impl AsyncDisown for tokio::net::UnixStream {
+    type Error = io::Error;
+
+    async fn disown_async(mut self) -> io::Result<()> {
+        crate::close::shutdown(tokio::io::AsyncWriteExt::shutdown(&mut self).await)
+    }
+}
@@ -0,0 +1,397 @@
+//! Dropping values on a dedicated background thread.
+//!
+//! Freeing a large `HashMap` or a `Vec<Vec<_>>` can cost milliseconds of
+//! `free()` calls. [`DisownInBackground::disown_in_background`] hands such
+//! values to a drop thread instead, so that the caller can carry on.
+//!
+//! ```
+//! use disown::DisownInBackground;
+//! use std::collections::HashMap;
+//!
+//! let cache: HashMap<u32, Vec<u8>> = (0..1000).map(|n| (n, vec![0; 64])).collect();
+//! cache.disown_in_background();
+//!
+//! // Wait until everything sent so far has actually been dropped.
+//! disown::background::flush();
+//! ```
+//!
+//! A process-wide drop thread is started lazily on first use, with the
+//! settings given to [`configure`] if it was called beforehand. Statics are
+//! never dropped, so call [`shutdown`] before `main` returns to guarantee that
+//! nothing still in its queue is leaked. A private [`DropThread`] can also be
+//! built with its own capacity and [`Backpressure`] policy.
+//!
+//! Panics raised by destructors on a drop thread are handled according to the
+//! global [`PanicPolicy`](crate::unwind::PanicPolicy).
+
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+
+use crate::unwind::{self, DisownCatchUnwind};
+
+/// Default number of values that may wait in a drop thread's queue.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// What to do when a drop thread's queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backpressure {
+    /// Block the sender until there is room in the queue.
+    #[default]
+    Block,
+    /// Drop the value immediately on the sending thread.
+    DropInline,
+}
+
+/// Configuration for a [`DropThread`].
+#[derive(Debug, Clone)]
+pub struct Builder {
+    name: String,
+    capacity: usize,
+    backpressure: Backpressure,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            name: "disown-drop".to_string(),
+            capacity: DEFAULT_CAPACITY,
+            backpressure: Backpressure::default(),
+        }
+    }
+}
+
+impl Builder {
+    /// A `Builder` with the default settings.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// The name given to the spawned thread.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// The number of values that may be queued before [`Backpressure`]
+    /// applies. A capacity of `0` makes every send a rendezvous.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// The policy to apply when the queue is full.
+    pub fn backpressure(mut self, backpressure: Backpressure) -> Self {
+        self.backpressure = backpressure;
+        self
+    }
+
+    /// Spawn the drop thread.
+    pub fn spawn(self) -> std::io::Result<DropThread> {
+        let (sender, receiver) = mpsc::sync_channel::<Box<dyn Send>>(self.capacity);
+        let pending = Arc::new(Pending::default());
+        let worker = pending.clone();
+
+        let handle = std::thread::Builder::new().name(self.name).spawn(move || {
+            // If a rethrown panic kills this thread, everything still
+            // queued is dropped along with the receiver. `flush` mustn't
+            // wait for it.
+            crate::defer! { worker.abandon() }
+
+            for value in receiver {
+                let result = value.disown_catch_unwind();
+                worker.finish();
+
+                if let Err(payload) = result {
+                    unwind::handle(payload);
+                }
+            }
+        })?;
+
+        Ok(DropThread {
+            queue: Some(Queue {
+                sender,
+                pending,
+                backpressure: self.backpressure,
+            }),
+            handle: Some(handle),
+        })
+    }
+}
+
+/// A count of values that have been sent but not yet dropped.
+#[derive(Default)]
+struct Pending {
+    count: Mutex<usize>,
+    done: Condvar,
+}
+
+impl Pending {
+    fn start(&self) {
+        *self.count.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+    }
+
+    fn finish(&self) {
+        let mut count = self.count.lock().unwrap_or_else(|e| e.into_inner());
+        *count = count.saturating_sub(1);
+        if *count == 0 {
+            self.done.notify_all();
+        }
+    }
+
+    fn abandon(&self) {
+        *self.count.lock().unwrap_or_else(|e| e.into_inner()) = 0;
+        self.done.notify_all();
+    }
+
+    fn get(&self) -> usize {
+        *self.count.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn wait(&self) {
+        let count = self.count.lock().unwrap_or_else(|e| e.into_inner());
+        drop(
+            self.done
+                .wait_while(count, |c| *c > 0)
+                .unwrap_or_else(|e| e.into_inner()),
+        );
+    }
+}
+
+/// The sending half of a drop thread.
+#[derive(Clone)]
+struct Queue {
+    sender: SyncSender<Box<dyn Send>>,
+    pending: Arc<Pending>,
+    backpressure: Backpressure,
+}
+
+impl Queue {
+    fn send<T: Send + 'static>(&self, value: T) {
+        let value: Box<dyn Send> = Box::new(value);
+        self.pending.start();
+
+        let result = match self.backpressure {
+            Backpressure::Block => self.sender.send(value).map_err(|e| e.0),
+            Backpressure::DropInline => self.sender.try_send(value).map_err(|e| match e {
+                TrySendError::Full(v) | TrySendError::Disconnected(v) => v,
+            }),
+        };
+
+        if let Err(value) = result {
+            drop(value);
+            self.pending.finish();
+        }
+    }
+}
+
+/// A thread whose only job is to drop the values sent to it.
+///
+/// Dropping a `DropThread` shuts it down cleanly: everything already queued is
+/// dropped before the thread is joined.
+///
+/// ```
+/// use disown::background::{Backpressure, DropThread};
+///
+/// let thread = DropThread::builder()
+///     .capacity(16)
+///     .backpressure(Backpressure::DropInline)
+///     .spawn()
+///     .unwrap();
+///
+/// for _ in 0..100 {
+///     thread.send(vec![0u8; 1024]);
+/// }
+///
+/// thread.flush();
+/// assert_eq!(0, thread.pending());
+/// thread.shutdown();
+/// ```
+pub struct DropThread {
+    queue: Option<Queue>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl DropThread {
+    /// Spawn a drop thread with the default settings.
+    pub fn new() -> std::io::Result<DropThread> {
+        Builder::new().spawn()
+    }
+
+    /// Configure a drop thread before spawning it.
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
+    /// Send a value to be dropped on this thread.
+    pub fn send<T: Send + 'static>(&self, value: T) {
+        match &self.queue {
+            Some(queue) => queue.send(value),
+            None => drop(value),
+        }
+    }
+
+    /// Block until every value sent so far has been dropped.
+    pub fn flush(&self) {
+        if let Some(queue) = &self.queue {
+            queue.pending.wait();
+        }
+    }
+
+    /// The number of values sent but not yet dropped.
+    pub fn pending(&self) -> usize {
+        self.queue.as_ref().map_or(0, |q| q.pending.get())
+    }
+
+    /// Drop everything still queued, then stop the thread.
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        // Closing the channel ends the worker's loop once the queue is empty.
+        self.queue.take();
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for DropThread {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl std::fmt::Debug for DropThread {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DropThread")
+            .field("pending", &self.pending())
+            .finish()
+    }
+}
+
+/// The process-wide drop thread.
+static GLOBAL: Mutex<Global> = Mutex::new(Global::Idle);
+
+enum Global {
+    /// Not yet started.
+    Idle,
+    /// Not yet started, but configured.
+    Configured(Builder),
+    Running(DropThread),
+    /// Shut down, or could not be spawned.
+    Stopped,
+}
+
+impl Global {
+    fn queue(&self) -> Option<Queue> {
+        match self {
+            Global::Running(thread) => thread.queue.clone(),
+            _ => None,
+        }
+    }
+}
+
+/// Set up the process-wide drop thread. This only works before it is first
+/// used; otherwise the given builder is handed back.
+///
+/// ```
+/// use disown::background::{self, Backpressure, Builder};
+/// use disown::DisownInBackground;
+///
+/// let builder = Builder::new()
+///     .capacity(16)
+///     .backpressure(Backpressure::DropInline);
+///
+/// background::configure(builder).unwrap();
+///
+/// for _ in 0..100 {
+///     vec![0u8; 1024].disown_in_background();
+/// }
+///
+/// background::flush();
+/// assert!(background::configure(Builder::new()).is_err());
+/// ```
+pub fn configure(builder: Builder) -> Result<(), Builder> {
+    let mut global = GLOBAL.lock().unwrap_or_else(|e| e.into_inner());
+
+    match *global {
+        Global::Idle | Global::Configured(_) => {
+            *global = Global::Configured(builder);
+            Ok(())
+        }
+        Global::Running(_) | Global::Stopped => Err(builder),
+    }
+}
+
+/// Send a value to the process-wide drop thread, starting it if necessary.
+///
+/// After [`shutdown`] (or if the thread could not be spawned) the value is
+/// dropped inline.
+pub fn send<T: Send + 'static>(value: T) {
+    let queue = {
+        let mut global = GLOBAL.lock().unwrap_or_else(|e| e.into_inner());
+
+        let builder = match &*global {
+            Global::Idle => Some(Builder::new()),
+            Global::Configured(builder) => Some(builder.clone()),
+            _ => None,
+        };
+
+        if let Some(builder) = builder {
+            *global = match builder.spawn() {
+                Ok(thread) => Global::Running(thread),
+                Err(_) => Global::Stopped,
+            };
+        }
+
+        global.queue()
+    };
+
+    // Never block on a full queue while holding the global lock.
+    match queue {
+        Some(queue) => queue.send(value),
+        None => drop(value),
+    }
+}
+
+/// Block until every value sent to the process-wide drop thread so far has
+/// been dropped.
+pub fn flush() {
+    let queue = GLOBAL.lock().unwrap_or_else(|e| e.into_inner()).queue();
+
+    if let Some(queue) = queue {
+        queue.pending.wait();
+    }
+}
+
+/// Drop everything queued on the process-wide drop thread and stop it.
+///
+/// Call this before `main` returns. Values disowned in the background
+/// afterwards are dropped inline.
+pub fn shutdown() {
+    let old = std::mem::replace(
+        &mut *GLOBAL.lock().unwrap_or_else(|e| e.into_inner()),
+        Global::Stopped,
+    );
+
+    if let Global::Running(thread) = old {
+        thread.shutdown();
+    }
+}
+
+/// Consume ownership on a background thread.
+///
+/// Implemented for every `T` that can be sent to another thread.
+pub trait DisownInBackground {
+    /// Send `self` to the process-wide drop thread.
+    fn disown_in_background(self);
+}
+
+impl<T: Send + 'static> DisownInBackground for T {
+    fn disown_in_background(self) {
+        send(self)
+    }
+}
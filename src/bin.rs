@@ -0,0 +1,314 @@
+//! Collecting values now, and dropping them at a quieter moment.
+//!
+//! In a game loop or a request loop, the middle of a frame is a bad time to
+//! free a large structure. [`DisownInto::disown_into`] moves a value into a
+//! [`DisposalBin`] instead, and the bin is emptied when there is time to spare.
+//!
+//! ```
+//! use disown::bin::{DisownInto, DisposalBin};
+//! use std::time::Duration;
+//!
+//! let mut bin = DisposalBin::new();
+//!
+//! for frame in 0..10 {
+//!     let scratch = vec![frame; 1000];
+//!     // ... use it ...
+//!     scratch.disown_into(&mut bin);
+//!
+//!     // Spend at most a millisecond per frame on dropping.
+//!     bin.empty_for(Duration::from_millis(1));
+//! }
+//!
+//! bin.empty();
+//! assert!(bin.is_empty());
+//! ```
+//!
+//! The bin keeps a rough count of the bytes waiting to be freed. Values that
+//! implement [`HeapSize`] can be added with
+//! [`DisownInto::disown_into_sized`] to count the heap memory they own as
+//! well as their inline size.
+//!
+//! ```
+//! use disown::bin::{DisownInto, DisposalBin};
+//!
+//! let mut bin = DisposalBin::new();
+//! let scratch: Vec<u64> = Vec::with_capacity(1000);
+//!
+//! scratch.disown_into_sized(&mut bin);
+//! assert!(bin.pending_bytes() >= 8000);
+//!
+//! bin.empty();
+//! assert_eq!(0, bin.pending_bytes());
+//! ```
+//!
+//! Panics raised while emptying a bin are handled according to the global
+//! [`PanicPolicy`](crate::unwind::PanicPolicy).
+
+use std::any::Any;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque};
+use std::mem::size_of;
+use std::time::{Duration, Instant};
+
+use crate::unwind::{self, DisownCatchUnwind};
+
+struct Entry {
+    value: Box<dyn Any>,
+    bytes: usize,
+}
+
+/// A holding area for values to be dropped later, oldest first.
+///
+/// Anything still in the bin when it is itself dropped is dropped with it.
+#[derive(Default)]
+pub struct DisposalBin {
+    entries: VecDeque<Entry>,
+    bytes: usize,
+}
+
+impl DisposalBin {
+    /// An empty bin.
+    pub fn new() -> Self {
+        DisposalBin::default()
+    }
+
+    /// Add a value to the bin, counting only its inline size,
+    /// `size_of::<T>()`, towards [`DisposalBin::pending_bytes`]. For a `Vec`
+    /// or a `HashMap` that is a few dozen bytes, however much it owns; use
+    /// [`DisposalBin::push_sized`] or [`DisownInto::disown_into_sized`] to
+    /// count that too.
+    pub fn push<T: 'static>(&mut self, value: T) {
+        self.push_sized(value, size_of::<T>());
+    }
+
+    /// Add a value to the bin, counting the given number of bytes towards
+    /// [`DisposalBin::pending_bytes`] instead of its inline size. Useful when
+    /// the value owns heap memory whose size is known.
+    ///
+    /// ```
+    /// use disown::bin::DisposalBin;
+    ///
+    /// let mut bin = DisposalBin::new();
+    /// let buffer = vec![0u8; 4096];
+    /// let bytes = buffer.capacity();
+    ///
+    /// bin.push_sized(buffer, bytes);
+    /// assert_eq!(4096, bin.pending_bytes());
+    /// ```
+    pub fn push_sized<T: 'static>(&mut self, value: T, bytes: usize) {
+        self.bytes += bytes;
+        self.entries.push_back(Entry {
+            value: Box::new(value),
+            bytes,
+        });
+    }
+
+    /// How many values are waiting to be dropped.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Is the bin empty?
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Roughly how many bytes the values waiting to be dropped hold.
+    ///
+    /// Heap memory is only included for values added with
+    /// [`DisownInto::disown_into_sized`] or [`DisposalBin::push_sized`]; for
+    /// the rest, only their inline size is counted.
+    pub fn pending_bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Drop everything in the bin.
+    pub fn empty(&mut self) {
+        while self.drop_one() {}
+    }
+
+    /// Drop values until the time budget is spent, returning `true` if the
+    /// bin is now empty. At least one value is always dropped, so that a bin
+    /// given too small a budget still makes progress.
+    pub fn empty_for(&mut self, budget: Duration) -> bool {
+        let start = Instant::now();
+
+        while self.drop_one() {
+            if start.elapsed() >= budget {
+                break;
+            }
+        }
+
+        self.is_empty()
+    }
+
+    /// Drop the oldest value, returning `false` if there was none.
+    fn drop_one(&mut self) -> bool {
+        match self.entries.pop_front() {
+            None => false,
+            Some(entry) => {
+                self.bytes -= entry.bytes;
+
+                if let Err(payload) = entry.value.disown_catch_unwind() {
+                    unwind::handle(payload);
+                }
+
+                true
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for DisposalBin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DisposalBin")
+            .field("len", &self.len())
+            .field("pending_bytes", &self.bytes)
+            .finish()
+    }
+}
+
+/// Consume ownership by moving a value into a [`DisposalBin`].
+pub trait DisownInto {
+    /// Add `self` to the bin, to be dropped when it is next emptied. Only its
+    /// inline size is counted; see [`DisposalBin::push`].
+    fn disown_into(self, bin: &mut DisposalBin);
+
+    /// Add `self` to the bin like [`DisownInto::disown_into`], counting the
+    /// heap memory it owns as well as its inline size.
+    fn disown_into_sized(self, bin: &mut DisposalBin)
+    where
+        Self: HeapSize;
+}
+
+impl<T: 'static> DisownInto for T {
+    fn disown_into(self, bin: &mut DisposalBin) {
+        bin.push(self)
+    }
+
+    fn disown_into_sized(self, bin: &mut DisposalBin)
+    where
+        Self: HeapSize,
+    {
+        let bytes = size_of::<T>() + self.heap_bytes();
+        bin.push_sized(self, bytes)
+    }
+}
+
+/// Types that can say roughly how much heap memory they own.
+pub trait HeapSize {
+    /// Roughly how many bytes this value owns on the heap, not counting
+    /// itself.
+    fn heap_bytes(&self) -> usize {
+        0
+    }
+}
+
+macro_rules! heap_size {
+    ($($t:ty),* $(,)?) => {
+        $(impl HeapSize for $t {})*
+    };
+}
+
+trivial_types!(heap_size);
+
+impl HeapSize for String {
+    fn heap_bytes(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl HeapSize for Box<str> {
+    fn heap_bytes(&self) -> usize {
+        self.len()
+    }
+}
+
+impl HeapSize for std::ffi::OsString {
+    fn heap_bytes(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl HeapSize for std::path::PathBuf {
+    fn heap_bytes(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_bytes(&self) -> usize {
+        self.as_ref().map_or(0, |v| v.heap_bytes())
+    }
+}
+
+impl<T: HeapSize> HeapSize for Box<T> {
+    fn heap_bytes(&self) -> usize {
+        size_of::<T>() + (**self).heap_bytes()
+    }
+}
+
+impl<T: HeapSize, const N: usize> HeapSize for [T; N] {
+    fn heap_bytes(&self) -> usize {
+        self.iter().map(|v| v.heap_bytes()).sum()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_bytes(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(|v| v.heap_bytes()).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for VecDeque<T> {
+    fn heap_bytes(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(|v| v.heap_bytes()).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for BinaryHeap<T> {
+    fn heap_bytes(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(|v| v.heap_bytes()).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for BTreeSet<T> {
+    fn heap_bytes(&self) -> usize {
+        self.iter().map(|v| size_of::<T>() + v.heap_bytes()).sum()
+    }
+}
+
+impl<K: HeapSize, V: HeapSize> HeapSize for BTreeMap<K, V> {
+    fn heap_bytes(&self) -> usize {
+        self.iter()
+            .map(|(k, v)| size_of::<(K, V)>() + k.heap_bytes() + v.heap_bytes())
+            .sum()
+    }
+}
+
+impl<T: HeapSize, S> HeapSize for HashSet<T, S> {
+    fn heap_bytes(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(|v| v.heap_bytes()).sum::<usize>()
+    }
+}
+
+impl<K: HeapSize, V: HeapSize, S> HeapSize for HashMap<K, V, S> {
+    fn heap_bytes(&self) -> usize {
+        self.capacity() * size_of::<(K, V)>()
+            + self
+                .iter()
+                .map(|(k, v)| k.heap_bytes() + v.heap_bytes())
+                .sum::<usize>()
+    }
+}
+
+impl<A: HeapSize, B: HeapSize> HeapSize for (A, B) {
+    fn heap_bytes(&self) -> usize {
+        self.0.heap_bytes() + self.1.heap_bytes()
+    }
+}
+
+impl<A: HeapSize, B: HeapSize, C: HeapSize> HeapSize for (A, B, C) {
+    fn heap_bytes(&self) -> usize {
+        self.0.heap_bytes() + self.1.heap_bytes() + self.2.heap_bytes()
+    }
+}
@@ -0,0 +1,111 @@
+//! Fallible disposal.
+//!
+//! `Drop` can't report errors, so disowning a `BufWriter<File>` silently
+//! throws away any failure to flush it. The [`Close`] trait releases a
+//! resource on purpose and hands back whatever went wrong.
+//!
+//! ```
+//! use disown::Close;
+//! use std::io::{BufWriter, Write};
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let path = std::env::temp_dir().join("disown-close-example.txt");
+//! let mut out = BufWriter::new(std::fs::File::create(&path)?);
+//!
+//! writeln!(out, "Hello!")?;
+//! out.disown_checked()?;
+//! # std::fs::remove_file(&path)
+//! # }
+//! ```
+//!
+//! Types whose drop can't fail are covered by the [`PlainDrop`] marker, so
+//! that generic code may call `disown_checked` on them as well.
+
+use std::io::{self, BufWriter, ErrorKind, LineWriter, Write};
+use std::net::{Shutdown, TcpStream};
+
+/// Consume ownership, reporting any error that a plain drop would swallow.
+pub trait Close {
+    /// Release `self`, returning the first error encountered while doing so.
+    fn disown_checked(self) -> io::Result<()>;
+}
+
+/// Types whose drop has nothing to report.
+///
+/// Every `PlainDrop` type is [`Close`], and closing it is just a drop.
+pub trait PlainDrop {}
+
+impl<T: PlainDrop> Close for T {
+    fn disown_checked(self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+macro_rules! plain_drop {
+    ($($t:ty),* $(,)?) => {
+        $(impl PlainDrop for $t {})*
+    };
+}
+
+trivial_types!(plain_drop);
+plain_drop!(String, std::ffi::OsString, std::path::PathBuf);
+
+impl<T: PlainDrop> PlainDrop for Option<T> {}
+impl<T: PlainDrop> PlainDrop for Box<T> {}
+impl<T: PlainDrop> PlainDrop for Vec<T> {}
+impl<T: PlainDrop> PlainDrop for std::collections::VecDeque<T> {}
+impl<T: PlainDrop> PlainDrop for std::collections::BTreeSet<T> {}
+impl<T: PlainDrop, S> PlainDrop for std::collections::HashSet<T, S> {}
+impl<K: PlainDrop, V: PlainDrop> PlainDrop for std::collections::BTreeMap<K, V> {}
+impl<K: PlainDrop, V: PlainDrop, S> PlainDrop for std::collections::HashMap<K, V, S> {}
+impl<T: PlainDrop, const N: usize> PlainDrop for [T; N] {}
+
+/// Flushes all data and metadata to disk with [`std::fs::File::sync_all`].
+impl Close for std::fs::File {
+    fn disown_checked(self) -> io::Result<()> {
+        self.sync_all()
+    }
+}
+
+/// Flushes the buffer, then closes the underlying writer.
+impl<W: Write + Close> Close for BufWriter<W> {
+    fn disown_checked(self) -> io::Result<()> {
+        self.into_inner()
+            .map_err(|e| e.into_error())?
+            .disown_checked()
+    }
+}
+
+/// Flushes the buffer, then closes the underlying writer.
+impl<W: Write + Close> Close for LineWriter<W> {
+    fn disown_checked(self) -> io::Result<()> {
+        self.into_inner()
+            .map_err(|e| e.into_error())?
+            .disown_checked()
+    }
+}
+
+/// Shuts down both halves of the connection. A peer that has already gone
+/// away is not an error.
+impl Close for TcpStream {
+    fn disown_checked(self) -> io::Result<()> {
+        shutdown(self.shutdown(Shutdown::Both))
+    }
+}
+
+/// Shuts down both halves of the connection. A peer that has already gone
+/// away is not an error.
+#[cfg(unix)]
+impl Close for std::os::unix::net::UnixStream {
+    fn disown_checked(self) -> io::Result<()> {
+        shutdown(self.shutdown(Shutdown::Both))
+    }
+}
+
+/// Treat a peer that has already gone away as a successful shutdown.
+pub(crate) fn shutdown(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == ErrorKind::NotConnected => Ok(()),
+        r => r,
+    }
+}
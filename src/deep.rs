@@ -0,0 +1,284 @@
+//! Stack-safe destruction of deeply recursive structures.
+//!
+//! The compiler-generated `Drop` of a `Box`-linked list or a deep tree
+//! recurses once per level, so dropping a long enough one overflows the stack.
+//! [`DeepDisown::deep_disown`] tears such structures down iteratively instead,
+//! using an explicit [`WorkList`] on the heap.
+//!
+//! ```
+//! use disown::deep::{DeepDisown, WorkList};
+//!
+//! struct List {
+//!     value: u64,
+//!     next: Option<Box<List>>,
+//! }
+//!
+//! impl DeepDisown for List {
+//!     fn push_children<'a>(self, work: &mut WorkList<'a>)
+//!     where
+//!         Self: 'a,
+//!     {
+//!         self.next.push_children(work);
+//!     }
+//! }
+//!
+//! let mut list = List { value: 0, next: None };
+//! for value in 1..1_000_000 {
+//!     list = List { value, next: Some(Box::new(list)) };
+//! }
+//!
+//! // A plain `drop(list)` would overflow the stack here.
+//! list.deep_disown();
+//! ```
+//!
+//! With the `derive` feature, `#[derive(DeepDisown)]` writes the impl above.
+//! Fields whose types don't implement [`DeepDisown`] can be marked with
+//! `#[deep_disown(shallow)]` to be dropped normally. Types that implement
+//! `Drop` themselves can't be destructured, and so can't be derived.
+//!
+//! ```
+//! # #[cfg(feature = "derive")]
+//! # {
+//! use disown::DeepDisown;
+//!
+//! #[derive(DeepDisown)]
+//! enum Expr {
+//!     Lit(i64),
+//!     Add(Box<Expr>, Box<Expr>),
+//!     Call {
+//!         name: String,
+//!         args: Vec<Expr>,
+//!         #[deep_disown(shallow)]
+//!         span: std::ops::Range<usize>,
+//!     },
+//! }
+//!
+//! let mut expr = Expr::Lit(0);
+//! for n in 1..1_000_000 {
+//!     expr = Expr::Add(Box::new(expr), Box::new(Expr::Lit(n)));
+//! }
+//!
+//! expr.deep_disown();
+//! # }
+//! ```
+
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Consume ownership without recursing on the stack.
+pub trait DeepDisown {
+    /// Whether dropping this type is known never to recurse deeply. Such values
+    /// are dropped on the spot instead of being queued on the [`WorkList`].
+    const SHALLOW: bool = false;
+
+    /// Move every owned child out of `self` and onto the work-list, leaving
+    /// only a shallow remainder to be dropped.
+    ///
+    /// Fields stored inline may have this called on them directly. Anything
+    /// behind an indirection must go through [`WorkList::push`] or its
+    /// siblings, which is what the impls for `Box`, `Vec`, `Rc` and `Arc` do.
+    fn push_children<'a>(self, work: &mut WorkList<'a>)
+    where
+        Self: Sized + 'a;
+
+    /// Drop `self` and everything it owns, iteratively.
+    fn deep_disown(self)
+    where
+        Self: Sized,
+    {
+        let mut work = WorkList { items: Vec::new() };
+        self.push_children(&mut work);
+        work.run();
+    }
+}
+
+/// A unit of pending destruction.
+trait Item<'a> {
+    /// Push this item's children, then drop whatever remains of it.
+    fn step(self: Box<Self>, work: &mut WorkList<'a>);
+}
+
+impl<'a, T: DeepDisown + 'a> Item<'a> for T {
+    fn step(self: Box<Self>, work: &mut WorkList<'a>) {
+        (*self).push_children(work)
+    }
+}
+
+/// The not-yet-visited elements of a collection.
+struct Drain<I>(I);
+
+impl<'a, I> Item<'a> for Drain<I>
+where
+    I: Iterator + 'a,
+    I::Item: DeepDisown + 'a,
+{
+    fn step(mut self: Box<Self>, work: &mut WorkList<'a>) {
+        if let Some(next) = self.0.next() {
+            // Reuse this allocation for the rest of the collection.
+            work.items.push(self);
+            next.push_children(work);
+        }
+    }
+}
+
+/// Values waiting to be torn down by [`DeepDisown::deep_disown`].
+pub struct WorkList<'a> {
+    items: Vec<Box<dyn Item<'a> + 'a>>,
+}
+
+impl<'a> WorkList<'a> {
+    /// Queue a value for destruction.
+    pub fn push<T: DeepDisown + 'a>(&mut self, value: T) {
+        if !T::SHALLOW {
+            self.items.push(Box::new(value));
+        }
+    }
+
+    /// Queue a boxed value for destruction, reusing its allocation.
+    pub fn push_box<T: DeepDisown + 'a>(&mut self, value: Box<T>) {
+        if !T::SHALLOW {
+            self.items.push(value);
+        }
+    }
+
+    /// Queue every element of a collection for destruction.
+    pub fn push_all<I>(&mut self, values: I)
+    where
+        I: IntoIterator,
+        I::IntoIter: 'a,
+        I::Item: DeepDisown + 'a,
+    {
+        if !I::Item::SHALLOW {
+            self.items.push(Box::new(Drain(values.into_iter())));
+        }
+    }
+
+    fn run(mut self) {
+        while let Some(item) = self.items.pop() {
+            item.step(&mut self);
+        }
+    }
+}
+
+impl std::fmt::Debug for WorkList<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WorkList")
+            .field("pending", &self.items.len())
+            .finish()
+    }
+}
+
+macro_rules! shallow {
+    ($($t:ty),* $(,)?) => {
+        $(
+            impl DeepDisown for $t {
+                const SHALLOW: bool = true;
+
+                fn push_children<'a>(self, _: &mut WorkList<'a>)
+                where
+                    Self: 'a,
+                {
+                }
+            }
+        )*
+    };
+}
+
+trivial_types!(shallow);
+shallow!(String, Box<str>, std::ffi::OsString, std::path::PathBuf);
+
+impl<T: ?Sized> DeepDisown for &T {
+    const SHALLOW: bool = true;
+
+    fn push_children<'a>(self, _: &mut WorkList<'a>)
+    where
+        Self: 'a,
+    {
+    }
+}
+
+impl<T: DeepDisown> DeepDisown for Box<T> {
+    fn push_children<'a>(self, work: &mut WorkList<'a>)
+    where
+        Self: 'a,
+    {
+        work.push_box(self)
+    }
+}
+
+impl<T: DeepDisown> DeepDisown for Vec<T> {
+    fn push_children<'a>(self, work: &mut WorkList<'a>)
+    where
+        Self: 'a,
+    {
+        work.push_all(self)
+    }
+}
+
+impl<T: DeepDisown> DeepDisown for Box<[T]> {
+    fn push_children<'a>(self, work: &mut WorkList<'a>)
+    where
+        Self: 'a,
+    {
+        work.push_all(self.into_vec())
+    }
+}
+
+impl<T: DeepDisown> DeepDisown for Option<T> {
+    const SHALLOW: bool = T::SHALLOW;
+
+    fn push_children<'a>(self, work: &mut WorkList<'a>)
+    where
+        Self: 'a,
+    {
+        if let Some(value) = self {
+            value.push_children(work);
+        }
+    }
+}
+
+/// Only the last strong reference tears down the contents.
+impl<T: DeepDisown> DeepDisown for Rc<T> {
+    fn push_children<'a>(self, work: &mut WorkList<'a>)
+    where
+        Self: 'a,
+    {
+        if let Ok(value) = Rc::try_unwrap(self) {
+            work.push(value);
+        }
+    }
+}
+
+/// Only the last strong reference tears down the contents.
+impl<T: DeepDisown> DeepDisown for Arc<T> {
+    fn push_children<'a>(self, work: &mut WorkList<'a>)
+    where
+        Self: 'a,
+    {
+        if let Ok(value) = Arc::try_unwrap(self) {
+            work.push(value);
+        }
+    }
+}
+
+macro_rules! tuple {
+    ($($t:ident),+) => {
+        impl<$($t: DeepDisown),+> DeepDisown for ($($t,)+) {
+            const SHALLOW: bool = $($t::SHALLOW)&&+;
+
+            #[allow(non_snake_case)]
+            fn push_children<'a>(self, work: &mut WorkList<'a>)
+            where
+                Self: 'a,
+            {
+                let ($($t,)+) = self;
+                $($t.push_children(work);)+
+            }
+        }
+    };
+}
+
+tuple!(A);
+tuple!(A, B);
+tuple!(A, B, C);
+tuple!(A, B, C, D);
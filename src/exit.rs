@@ -0,0 +1,132 @@
+//! Dropping values when the process exits.
+//!
+//! Values held in a `static` or a [`OnceLock`](std::sync::OnceLock) are never
+//! dropped, so any flushing they do in their `Drop` impls never happens.
+//! [`disown_at_exit`] takes ownership of a value and drops it when `main`
+//! returns instead. Values are dropped in the reverse of the order they were
+//! registered, and may be registered from any thread.
+//!
+//! ```
+//! use disown::exit::{self, DisownAtExit};
+//! use disown::testing::DropTracker;
+//!
+//! let tracker = DropTracker::new();
+//! exit::disown_at_exit(tracker.token("log sink"));
+//! tracker.token("temp dir").disown_at_exit();
+//! assert_eq!(2, exit::pending());
+//!
+//! // This happens automatically once `main` returns.
+//! assert_eq!(2, exit::run());
+//! tracker.assert_drop_order(&["temp dir", "log sink"]);
+//! ```
+//!
+//! [`std::process::exit`] skips destructors but still runs these drops. Call
+//! [`exit()`] to be explicit about it. Returning from `main` after a panic
+//! also runs them, but an abort does not. With the `signal` feature,
+//! `on_signals` extends this to `SIGINT` and `SIGTERM`.
+//!
+//! Exit-time drops run after the main thread has finished, so they should not
+//! rely on thread-local storage. A drop that panics is handled according to
+//! the [`PanicPolicy`](crate::unwind::PanicPolicy), except that a rethrown
+//! panic aborts the process.
+
+use std::os::raw::c_int;
+use std::sync::{Mutex, Once};
+
+use crate::DisownCatchUnwind;
+
+static VALUES: Mutex<Vec<Box<dyn Send>>> = Mutex::new(Vec::new());
+
+static INSTALL: Once = Once::new();
+
+extern "C" {
+    fn atexit(callback: extern "C" fn()) -> c_int;
+}
+
+extern "C" fn at_exit() {
+    run();
+}
+
+/// Drop a value when the process exits.
+pub fn disown_at_exit<T: Send + 'static>(value: T) {
+    INSTALL.call_once(|| {
+        // SAFETY: `at_exit` is a plain function that lives as long as the
+        // process does.
+        if unsafe { atexit(at_exit) } != 0 {
+            eprintln!("warning: failed to register disown's exit handler");
+        }
+    });
+
+    VALUES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(Box::new(value));
+}
+
+/// The number of values waiting to be dropped at exit.
+pub fn pending() -> usize {
+    VALUES.lock().unwrap_or_else(|e| e.into_inner()).len()
+}
+
+/// Drop every registered value now, most recent first, returning how many
+/// there were. Values registered while this runs are dropped too.
+pub fn run() -> usize {
+    drain(&VALUES)
+}
+
+/// Drop values from the end of a list until it is empty.
+pub(crate) fn drain(values: &Mutex<Vec<Box<dyn Send>>>) -> usize {
+    let mut count = 0;
+
+    // The lock is released before each drop, so that a drop may register more
+    // values.
+    while let Some(value) = pop(values) {
+        if let Err(payload) = value.disown_catch_unwind() {
+            crate::unwind::handle(payload);
+        }
+
+        count += 1;
+    }
+
+    count
+}
+
+fn pop(values: &Mutex<Vec<Box<dyn Send>>>) -> Option<Box<dyn Send>> {
+    values.lock().unwrap_or_else(|e| e.into_inner()).pop()
+}
+
+/// Drop every registered value, then exit the process with the given code.
+///
+/// A drop-in replacement for [`std::process::exit`].
+pub fn exit(code: i32) -> ! {
+    run();
+    std::process::exit(code)
+}
+
+/// Also drop every registered value when the process receives `SIGINT` or
+/// `SIGTERM`, before terminating it as the signal normally would.
+///
+/// This is shorthand for installing a [`signal::Builder`](crate::signal::Builder)
+/// for those two signals; see the [`signal`](crate::signal) module.
+///
+/// Requires the `signal` feature, and is only available on Unix.
+#[cfg(all(unix, feature = "signal"))]
+pub fn on_signals() -> std::io::Result<()> {
+    use crate::signal::{Builder, Signal};
+
+    Builder::new()
+        .signals(&[Signal::Interrupt, Signal::Terminate])
+        .install()
+}
+
+/// Consume ownership, dropping the value when the process exits.
+pub trait DisownAtExit {
+    /// Drop `self` when the process exits. See [`disown_at_exit`].
+    fn disown_at_exit(self);
+}
+
+impl<T: Send + 'static> DisownAtExit for T {
+    fn disown_at_exit(self) {
+        disown_at_exit(self)
+    }
+}
@@ -0,0 +1,218 @@
+//! Scope guards: cleanup that runs when a value is dropped.
+//!
+//! A [`DisownGuard`] owns a value together with a closure, and hands the value
+//! to that closure when the guard goes out of scope. Whether it runs can
+//! depend on how the scope ended, which is decided by
+//! [`std::thread::panicking`].
+//!
+//! ```
+//! use disown::guard::DisownGuard;
+//!
+//! let mut log = Vec::new();
+//!
+//! {
+//!     let mut guard = DisownGuard::new(&mut log, |log| log.push("cleaned up"));
+//!     guard.push("working");
+//! }
+//!
+//! assert_eq!(vec!["working", "cleaned up"], log);
+//! ```
+//!
+//! The [`defer!`](crate::defer) and [`defer_on_unwind!`](crate::defer_on_unwind)
+//! macros cover the common case of running some code at the end of a scope.
+//!
+//! ```
+//! use disown::defer;
+//! use std::cell::Cell;
+//!
+//! let count = Cell::new(0);
+//!
+//! {
+//!     defer! { count.set(count.get() + 1) }
+//!     assert_eq!(0, count.get());
+//! }
+//!
+//! assert_eq!(1, count.get());
+//! ```
+
+use std::fmt;
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut};
+
+/// When a [`DisownGuard`] runs its closure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// However the scope ends.
+    Always,
+    /// Only when the scope ends normally.
+    OnSuccess,
+    /// Only when the scope ends because of a panic.
+    OnUnwind,
+}
+
+impl Strategy {
+    fn should_run(self) -> bool {
+        match self {
+            Strategy::Always => true,
+            Strategy::OnSuccess => !std::thread::panicking(),
+            Strategy::OnUnwind => std::thread::panicking(),
+        }
+    }
+}
+
+/// Owns a value, and passes it to a closure when dropped.
+///
+/// Derefs to the value, so the guard can be used in its place.
+pub struct DisownGuard<T, F>
+where
+    F: FnOnce(T),
+{
+    value: ManuallyDrop<T>,
+    cleanup: ManuallyDrop<F>,
+    strategy: Strategy,
+}
+
+impl<T, F> DisownGuard<T, F>
+where
+    F: FnOnce(T),
+{
+    /// Run `cleanup` on the value however the scope ends.
+    pub fn new(value: T, cleanup: F) -> Self {
+        DisownGuard::with_strategy(value, cleanup, Strategy::Always)
+    }
+
+    /// Run `cleanup` on the value only if the scope ends normally.
+    pub fn on_success(value: T, cleanup: F) -> Self {
+        DisownGuard::with_strategy(value, cleanup, Strategy::OnSuccess)
+    }
+
+    /// Run `cleanup` on the value only if the scope ends in a panic.
+    ///
+    /// ```
+    /// use disown::guard::DisownGuard;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    ///
+    /// static ROLLED_BACK: AtomicBool = AtomicBool::new(false);
+    ///
+    /// let result = std::panic::catch_unwind(|| {
+    ///     let _txn = DisownGuard::on_unwind((), |_| ROLLED_BACK.store(true, Ordering::SeqCst));
+    ///     panic!("Oh no!");
+    /// });
+    ///
+    /// assert!(result.is_err());
+    /// assert!(ROLLED_BACK.load(Ordering::SeqCst));
+    /// ```
+    pub fn on_unwind(value: T, cleanup: F) -> Self {
+        DisownGuard::with_strategy(value, cleanup, Strategy::OnUnwind)
+    }
+
+    /// Run `cleanup` on the value according to the given [`Strategy`].
+    pub fn with_strategy(value: T, cleanup: F, strategy: Strategy) -> Self {
+        DisownGuard {
+            value: ManuallyDrop::new(value),
+            cleanup: ManuallyDrop::new(cleanup),
+            strategy,
+        }
+    }
+
+    /// When this guard runs its closure.
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    /// Cancel the cleanup, and hand the value back.
+    ///
+    /// ```
+    /// use disown::guard::DisownGuard;
+    ///
+    /// let guard = DisownGuard::new(vec![1, 2, 3], |_| panic!("Never runs"));
+    /// assert_eq!(vec![1, 2, 3], guard.dismiss());
+    /// ```
+    pub fn dismiss(self) -> T {
+        let mut guard = ManuallyDrop::new(self);
+
+        // SAFETY: `guard` is never dropped, so each field is taken exactly
+        // once.
+        unsafe {
+            ManuallyDrop::drop(&mut guard.cleanup);
+            ManuallyDrop::take(&mut guard.value)
+        }
+    }
+}
+
+impl<T, F> Deref for DisownGuard<T, F>
+where
+    F: FnOnce(T),
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T, F> DerefMut for DisownGuard<T, F>
+where
+    F: FnOnce(T),
+{
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for DisownGuard<T, F>
+where
+    F: FnOnce(T),
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DisownGuard")
+            .field("value", &*self.value)
+            .field("strategy", &self.strategy)
+            .finish()
+    }
+}
+
+impl<T, F> Drop for DisownGuard<T, F>
+where
+    F: FnOnce(T),
+{
+    fn drop(&mut self) {
+        // SAFETY: `drop` is only called once, and the fields are never used
+        // again.
+        let (value, cleanup) = unsafe {
+            (
+                ManuallyDrop::take(&mut self.value),
+                ManuallyDrop::take(&mut self.cleanup),
+            )
+        };
+
+        if self.strategy.should_run() {
+            cleanup(value);
+        }
+    }
+}
+
+/// Run some code at the end of the current scope, however it ends.
+///
+/// See the [`guard`](crate::guard) module for an example.
+#[macro_export]
+macro_rules! defer {
+    ($($body:tt)*) => {
+        let _guard = $crate::guard::DisownGuard::new((), |()| { $($body)* });
+    };
+}
+
+/// Run some code at the end of the current scope, but only if it ends in a
+/// panic.
+///
+/// ```
+/// use disown::defer_on_unwind;
+///
+/// defer_on_unwind! { eprintln!("Never printed") }
+/// ```
+#[macro_export]
+macro_rules! defer_on_unwind {
+    ($($body:tt)*) => {
+        let _guard = $crate::guard::DisownGuard::on_unwind((), |()| { $($body)* });
+    };
+}
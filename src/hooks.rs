@@ -0,0 +1,144 @@
+//! Running code whenever values of a certain type are disowned.
+//!
+//! Hooks registered with [`on_disown`] are called by
+//! [`DisownHooked::disown_hooked`] with a reference to the value, just before
+//! it is dropped. This suits cross-cutting concerns, like auditing every
+//! discarded `Session`.
+//!
+//! ```
+//! use disown::hooks::{self, DisownHooked};
+//! use std::sync::atomic::{AtomicU32, Ordering};
+//!
+//! struct Session {
+//!     user: &'static str,
+//! }
+//!
+//! static DISCARDED: AtomicU32 = AtomicU32::new(0);
+//!
+//! let hook = hooks::on_disown(|s: &Session| {
+//!     assert_eq!("colin", s.user);
+//!     DISCARDED.fetch_add(1, Ordering::SeqCst);
+//! });
+//!
+//! Session { user: "colin" }.disown_hooked();
+//! assert_eq!(1, DISCARDED.load(Ordering::SeqCst));
+//!
+//! assert!(hooks::remove(hook));
+//! Session { user: "colin" }.disown_hooked();
+//! assert_eq!(1, DISCARDED.load(Ordering::SeqCst));
+//! ```
+//!
+//! Hooks for a type run in the order they were registered. They may be
+//! registered and removed from any thread at any time, including from within
+//! another hook.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+type Hook = Arc<dyn Fn(&dyn Any) + Send + Sync>;
+
+/// Identifies a registered hook, so that it can be removed again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HookId(u64);
+
+/// Each type's hooks, in registration order.
+type Registry = HashMap<TypeId, Vec<(HookId, Hook)>>;
+
+static HOOKS: RwLock<Option<Registry>> = RwLock::new(None);
+
+/// The total number of registered hooks, so that unhooked types can skip the
+/// lock entirely.
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Register a hook to run whenever a `T` is disowned with
+/// [`DisownHooked::disown_hooked`].
+pub fn on_disown<T, F>(hook: F) -> HookId
+where
+    T: 'static,
+    F: Fn(&T) + Send + Sync + 'static,
+{
+    let id = HookId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let hook: Hook = Arc::new(move |value: &dyn Any| {
+        if let Some(value) = value.downcast_ref::<T>() {
+            hook(value)
+        }
+    });
+
+    HOOKS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(HashMap::new)
+        .entry(TypeId::of::<T>())
+        .or_default()
+        .push((id, hook));
+    COUNT.fetch_add(1, Ordering::SeqCst);
+
+    id
+}
+
+/// Unregister a hook. Returns `false` if it had already been removed.
+pub fn remove(id: HookId) -> bool {
+    let mut hooks = HOOKS.write().unwrap_or_else(|e| e.into_inner());
+
+    let removed = hooks.iter_mut().flat_map(|m| m.values_mut()).any(|hs| {
+        let before = hs.len();
+        hs.retain(|(i, _)| *i != id);
+        hs.len() < before
+    });
+
+    if removed {
+        COUNT.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    removed
+}
+
+/// Unregister every hook for `T`, returning how many there were.
+pub fn clear<T: 'static>() -> usize {
+    let removed = HOOKS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_mut()
+        .and_then(|m| m.remove(&TypeId::of::<T>()))
+        .map_or(0, |hs| hs.len());
+
+    COUNT.fetch_sub(removed, Ordering::SeqCst);
+    removed
+}
+
+/// Run the hooks registered for `T` on a value.
+fn run<T: 'static>(value: &T) {
+    if COUNT.load(Ordering::SeqCst) == 0 {
+        return;
+    }
+
+    // Hooks are called outside the lock, so that they may themselves register
+    // or remove hooks.
+    let hooks: Vec<Hook> = HOOKS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .and_then(|m| m.get(&TypeId::of::<T>()))
+        .map(|hs| hs.iter().map(|(_, h)| h.clone()).collect())
+        .unwrap_or_default();
+
+    for hook in hooks {
+        hook(value);
+    }
+}
+
+/// Consume ownership, running any hooks registered for the type first.
+pub trait DisownHooked {
+    /// Call every hook registered for this type with `&self`, then drop it.
+    fn disown_hooked(self);
+}
+
+impl<T: 'static> DisownHooked for T {
+    fn disown_hooked(self) {
+        run(&self);
+    }
+}
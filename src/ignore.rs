@@ -0,0 +1,139 @@
+//! Discarding only the errors we expect.
+//!
+//! It is common to disown the `Result` of `remove_file` because a missing file
+//! is fine, but `.disown()` also throws away permission errors, full disks and
+//! everything else. [`DisownErr`] discards only the errors that match, and
+//! hands back all the others.
+//!
+//! ```
+//! use disown::DisownErr;
+//! use std::io::ErrorKind;
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let dir = std::env::temp_dir().join("disown-ignore-example");
+//!
+//! std::fs::create_dir(&dir).ignore_kind(ErrorKind::AlreadyExists)?;
+//! std::fs::create_dir(&dir).ignore_kind(ErrorKind::AlreadyExists)?;
+//! std::fs::remove_dir(&dir).ignore_kind(ErrorKind::NotFound)?;
+//! std::fs::remove_dir(&dir).ignore_kind(ErrorKind::NotFound)?;
+//!
+//! // Other errors still come through.
+//! let denied = std::fs::read_dir(&dir).ignore_kind([ErrorKind::PermissionDenied]);
+//! assert_eq!(ErrorKind::NotFound, denied.unwrap_err().kind());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Custom error types take part by implementing [`ErrorMatcher`].
+//!
+//! ```
+//! use disown::ignore::{DisownErr, ErrorMatcher};
+//!
+//! #[derive(Debug, PartialEq)]
+//! enum DbError {
+//!     Missing,
+//!     Conflict,
+//! }
+//!
+//! impl ErrorMatcher<DbError> for DbError {
+//!     fn matches(&self, error: &DbError) -> bool {
+//!         self == error
+//!     }
+//! }
+//!
+//! let deleted: Result<u32, DbError> = Err(DbError::Missing);
+//! assert_eq!(Ok(()), deleted.ignore_kind(DbError::Missing));
+//!
+//! let inserted: Result<u32, DbError> = Err(DbError::Conflict);
+//! assert_eq!(Err(DbError::Conflict), inserted.ignore_kind(DbError::Missing));
+//! ```
+
+use std::io::{self, ErrorKind};
+
+/// Decides whether an error of type `E` should be discarded.
+pub trait ErrorMatcher<E: ?Sized> {
+    /// Does this error match?
+    fn matches(&self, error: &E) -> bool;
+}
+
+impl<E: ?Sized, F: Fn(&E) -> bool> ErrorMatcher<E> for F {
+    fn matches(&self, error: &E) -> bool {
+        self(error)
+    }
+}
+
+impl ErrorMatcher<io::Error> for ErrorKind {
+    fn matches(&self, error: &io::Error) -> bool {
+        error.kind() == *self
+    }
+}
+
+impl<const N: usize> ErrorMatcher<io::Error> for [ErrorKind; N] {
+    fn matches(&self, error: &io::Error) -> bool {
+        self.contains(&error.kind())
+    }
+}
+
+impl ErrorMatcher<io::Error> for &[ErrorKind] {
+    fn matches(&self, error: &io::Error) -> bool {
+        self.contains(&error.kind())
+    }
+}
+
+/// Discard only the errors that match, keeping every other error.
+pub trait DisownErr<E>: Sized {
+    /// What remains once the success value and any matching error are gone.
+    type Output;
+
+    /// Discard the error if it satisfies the predicate.
+    fn disown_err_if<F>(self, f: F) -> Self::Output
+    where
+        F: FnOnce(&E) -> bool;
+
+    /// Discard the error if it matches, say, an [`ErrorKind`] or a set of
+    /// them.
+    fn ignore_kind<M>(self, matcher: M) -> Self::Output
+    where
+        M: ErrorMatcher<E>,
+    {
+        self.disown_err_if(|e| matcher.matches(e))
+    }
+}
+
+impl<T, E> DisownErr<E> for Result<T, E> {
+    type Output = Result<(), E>;
+
+    fn disown_err_if<F>(self, f: F) -> Result<(), E>
+    where
+        F: FnOnce(&E) -> bool,
+    {
+        match self {
+            Ok(_) => Ok(()),
+            Err(e) if f(&e) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// For optional errors, such as those from [`std::net::TcpStream::take_error`].
+///
+/// ```
+/// use disown::DisownErr;
+/// use std::io::{Error, ErrorKind};
+///
+/// let pending = Some(Error::from(ErrorKind::ConnectionReset));
+/// assert!(pending.ignore_kind(ErrorKind::ConnectionReset).is_none());
+/// ```
+impl<E> DisownErr<E> for Option<E> {
+    type Output = Option<E>;
+
+    fn disown_err_if<F>(self, f: F) -> Option<E>
+    where
+        F: FnOnce(&E) -> bool,
+    {
+        match self {
+            Some(e) if f(&e) => None,
+            other => other,
+        }
+    }
+}
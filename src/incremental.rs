@@ -0,0 +1,160 @@
+//! Tearing down huge collections a little at a time.
+//!
+//! Dropping a collection with tens of millions of entries can stall a thread
+//! for hundreds of milliseconds. [`DisownIncrementally::disown_incrementally`]
+//! returns an [`IncrementalDrop`] handle instead, which frees a bounded amount
+//! of it each time [`IncrementalDrop::step`] is called from a frame loop or
+//! an executor tick.
+//!
+//! ```
+//! use disown::DisownIncrementally;
+//! use std::collections::HashMap;
+//!
+//! let big: HashMap<u64, String> = (0..10_000).map(|n| (n, n.to_string())).collect();
+//! let mut teardown = big.disown_incrementally().batch(1000);
+//!
+//! let mut frames = 0;
+//! while !teardown.step() {
+//!     frames += 1;
+//! }
+//!
+//! assert_eq!(9, frames);
+//! assert_eq!(0, teardown.remaining());
+//! ```
+//!
+//! An `IncrementalDrop` is also a [`Future`], which performs one step per poll
+//! and yields in between, so it can be spawned onto an async executor.
+//!
+//! Dropping the handle itself drops whatever remains all at once.
+
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// How often the clock is checked while stepping with a time budget.
+const CLOCK_EVERY: usize = 32;
+
+/// How much work a single [`IncrementalDrop::step`] may do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// Drop at most this many elements.
+    Elements(usize),
+    /// Drop elements until this much time has passed.
+    Time(Duration),
+}
+
+/// A collection being dropped a step at a time.
+#[derive(Debug)]
+#[must_use = "dropping the handle frees the rest of the collection at once"]
+pub struct IncrementalDrop<I> {
+    iter: Option<I>,
+    limit: Limit,
+}
+
+impl<I: Iterator> IncrementalDrop<I> {
+    /// Start dropping the items of an iterator, 1024 per step by default.
+    pub fn new(iter: I) -> Self {
+        IncrementalDrop {
+            iter: Some(iter),
+            limit: Limit::Elements(1024),
+        }
+    }
+
+    /// Drop at most `n` elements per [`IncrementalDrop::step`].
+    pub fn batch(mut self, n: usize) -> Self {
+        self.limit = Limit::Elements(n.max(1));
+        self
+    }
+
+    /// Drop elements for about `budget` per [`IncrementalDrop::step`].
+    pub fn time_budget(mut self, budget: Duration) -> Self {
+        self.limit = Limit::Time(budget);
+        self
+    }
+
+    /// Do one step's worth of work, as configured. Returns `true` once
+    /// everything has been dropped.
+    pub fn step(&mut self) -> bool {
+        match self.limit {
+            Limit::Elements(n) => self.step_n(n),
+            Limit::Time(budget) => self.step_for(budget),
+        }
+    }
+
+    /// Drop at most `n` elements. Returns `true` once everything has been
+    /// dropped.
+    pub fn step_n(&mut self, n: usize) -> bool {
+        if let Some(iter) = self.iter.as_mut() {
+            let exhausted = iter.take(n).count() < n || iter.size_hint().1 == Some(0);
+
+            if exhausted {
+                self.finish();
+            }
+        }
+
+        self.is_done()
+    }
+
+    /// Drop elements until roughly `budget` has passed, always dropping at
+    /// least one. Returns `true` once everything has been dropped.
+    pub fn step_for(&mut self, budget: Duration) -> bool {
+        let start = Instant::now();
+
+        while !self.is_done() {
+            self.step_n(CLOCK_EVERY);
+
+            if start.elapsed() >= budget {
+                break;
+            }
+        }
+
+        self.is_done()
+    }
+
+    /// Drop everything that remains, right now.
+    pub fn finish(&mut self) {
+        self.iter.take();
+    }
+
+    /// Has everything been dropped?
+    pub fn is_done(&self) -> bool {
+        self.iter.is_none()
+    }
+
+    /// The number of elements still to be dropped, as far as the iterator
+    /// knows.
+    pub fn remaining(&self) -> usize {
+        self.iter.as_ref().map_or(0, |i| i.size_hint().0)
+    }
+}
+
+impl<I: Iterator + Unpin> Future for IncrementalDrop<I> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.step() {
+            Poll::Ready(())
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+/// Consume ownership of a collection gradually.
+pub trait DisownIncrementally: IntoIterator + Sized {
+    /// A handle that drops this collection's elements a step at a time.
+    fn disown_incrementally(self) -> IncrementalDrop<Self::IntoIter> {
+        IncrementalDrop::new(self.into_iter())
+    }
+}
+
+impl<T> DisownIncrementally for Vec<T> {}
+impl<T> DisownIncrementally for VecDeque<T> {}
+impl<T> DisownIncrementally for BinaryHeap<T> {}
+impl<T> DisownIncrementally for BTreeSet<T> {}
+impl<T, S> DisownIncrementally for HashSet<T, S> {}
+impl<K, V> DisownIncrementally for BTreeMap<K, V> {}
+impl<K, V, S> DisownIncrementally for HashMap<K, V, S> {}
@@ -0,0 +1,129 @@
+//! Skipping pointless frees while the process shuts down.
+//!
+//! Right before a process exits, freeing memory is wasted work: the operating
+//! system reclaims it all at once anyway. Compilers and batch tools save
+//! real time by not bothering. Once [`begin_shutdown`] has been called,
+//! [`DisownOrLeak::disown_or_leak`] forgets values instead of dropping them.
+//!
+//! ```
+//! use disown::bin::HeapSize;
+//! use disown::leak::{self, DisownOrLeak};
+//! use std::collections::HashMap;
+//!
+//! let symbols: HashMap<u32, String> = (0..1000).map(|n| (n, n.to_string())).collect();
+//! let owned = symbols.heap_bytes();
+//! let scratch = vec![0u8; 4096];
+//!
+//! // Before shutdown, this is an ordinary drop.
+//! scratch.disown_or_leak();
+//!
+//! leak::track_bytes(true);
+//! leak::begin_shutdown();
+//! symbols.disown_or_leak();
+//!
+//! let skipped = leak::skipped();
+//! assert_eq!(1, skipped.values);
+//! assert_eq!(owned, skipped.bytes);
+//! ```
+//!
+//! Only types marked [`PureMemory`] can be leaked this way, since their drops
+//! do nothing but free memory. Values whose drops have side effects, like
+//! flushing a file, aren't marked, and so still have to be dropped normally.
+//!
+//! Counting the skipped bytes means walking the value, which costs some of
+//! the time that leaking it saves, so it is off by default; see
+//! [`track_bytes`].
+
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::bin::HeapSize;
+
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+static TRACKING: AtomicBool = AtomicBool::new(false);
+static VALUES: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Enter shutdown mode, from which point [`DisownOrLeak::disown_or_leak`]
+/// leaks instead of dropping. There is no going back.
+pub fn begin_shutdown() {
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+}
+
+/// Has [`begin_shutdown`] been called?
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::Relaxed)
+}
+
+/// Whether to count the bytes owned by each leaked value, as reported by
+/// [`skipped`]. Off by default.
+pub fn track_bytes(yes: bool) {
+    TRACKING.store(yes, Ordering::SeqCst);
+}
+
+/// What shutdown mode has skipped so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Skipped {
+    /// The number of values leaked.
+    pub values: usize,
+    /// Roughly how many heap bytes they owned, counting only values leaked
+    /// while [`track_bytes`] was on. Their inline bytes aren't leaked, and so
+    /// aren't counted.
+    pub bytes: usize,
+}
+
+/// What shutdown mode has skipped so far.
+pub fn skipped() -> Skipped {
+    Skipped {
+        values: VALUES.load(Ordering::SeqCst),
+        bytes: BYTES.load(Ordering::SeqCst),
+    }
+}
+
+/// Types whose drop does nothing but free memory, so that skipping it is
+/// unobservable once the process is about to exit.
+///
+/// The bytes they own are measured through [`HeapSize`].
+pub trait PureMemory: HeapSize {}
+
+macro_rules! pure_memory {
+    ($($t:ty),* $(,)?) => {
+        $(impl PureMemory for $t {})*
+    };
+}
+
+trivial_types!(pure_memory);
+pure_memory!(String, Box<str>, std::ffi::OsString, std::path::PathBuf);
+
+impl<T: PureMemory> PureMemory for Option<T> {}
+impl<T: PureMemory> PureMemory for Box<T> {}
+impl<T: PureMemory, const N: usize> PureMemory for [T; N] {}
+impl<T: PureMemory> PureMemory for Vec<T> {}
+impl<T: PureMemory> PureMemory for VecDeque<T> {}
+impl<T: PureMemory> PureMemory for BinaryHeap<T> {}
+impl<T: PureMemory> PureMemory for BTreeSet<T> {}
+impl<K: PureMemory, V: PureMemory> PureMemory for BTreeMap<K, V> {}
+impl<T: PureMemory, S> PureMemory for HashSet<T, S> {}
+impl<K: PureMemory, V: PureMemory, S> PureMemory for HashMap<K, V, S> {}
+impl<A: PureMemory, B: PureMemory> PureMemory for (A, B) {}
+impl<A: PureMemory, B: PureMemory, C: PureMemory> PureMemory for (A, B, C) {}
+
+/// Consume ownership, or skip the drop entirely during shutdown.
+pub trait DisownOrLeak: PureMemory + Sized {
+    /// Drop `self`, unless [`begin_shutdown`] has been called, in which case
+    /// forget it instead.
+    fn disown_or_leak(self) {
+        if !is_shutting_down() {
+            return;
+        }
+
+        if TRACKING.load(Ordering::Relaxed) {
+            BYTES.fetch_add(self.heap_bytes(), Ordering::SeqCst);
+        }
+
+        VALUES.fetch_add(1, Ordering::SeqCst);
+        std::mem::forget(self);
+    }
+}
+
+impl<T: PureMemory> DisownOrLeak for T {}
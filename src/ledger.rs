@@ -0,0 +1,223 @@
+//! A record of who disowned what, for debugging.
+//!
+//! When a value vanishes unexpectedly, it helps to know who let go of it.
+//! [`DisownRecorded::disown_recorded`] is [`Disown::disown`](crate::Disown::disown)
+//! plus an entry in a global, bounded ledger: the type, the caller's source
+//! location, the thread, a timestamp, and optionally the value's `Debug`
+//! representation.
+//!
+//! ```
+//! use disown::ledger::{self, DisownRecorded};
+//!
+//! struct Session {
+//!     id: u32,
+//! }
+//!
+//! Session { id: 7 }.disown_recorded();
+//! vec![1, 2, 3].disown_recorded_debug();
+//!
+//! let sessions = ledger::by_type::<Session>();
+//! assert_eq!(1, sessions.len());
+//! assert_eq!(file!(), sessions[0].location.file());
+//!
+//! let last = ledger::entries().pop().unwrap();
+//! assert_eq!(Some("[1, 2, 3]"), last.debug.as_deref());
+//! ```
+//!
+//! Only the most recent entries are kept; see [`set_capacity`]. The ledger can
+//! be printed with [`dump`], and [`install_panic_hook`] does so automatically
+//! whenever a thread panics.
+//!
+//! Requires the `ledger` feature.
+
+use std::collections::VecDeque;
+use std::fmt::{self, Debug, Write};
+use std::panic::Location;
+use std::sync::{Mutex, TryLockError};
+use std::thread::ThreadId;
+use std::time::SystemTime;
+
+/// The number of entries kept by default.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// A single recorded disposal.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// The disowned type, as given by [`std::any::type_name`].
+    pub type_name: &'static str,
+    /// Where the value was disowned.
+    pub location: &'static Location<'static>,
+    /// The thread that disowned it.
+    pub thread: ThreadId,
+    /// That thread's name, if it has one.
+    pub thread_name: Option<String>,
+    /// When the value was disowned.
+    pub time: SystemTime,
+    /// The value's `Debug` representation, if it was recorded.
+    pub debug: Option<String>,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let since = self
+            .time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+
+        write!(
+            f,
+            "[{}.{:06}] {} disowned at {} on {}",
+            since.as_secs(),
+            since.subsec_micros(),
+            self.type_name,
+            self.location,
+            self.thread_name.as_deref().unwrap_or("<unnamed>"),
+        )?;
+
+        match &self.debug {
+            Some(debug) => write!(f, ": {}", debug),
+            None => Ok(()),
+        }
+    }
+}
+
+struct Ledger {
+    entries: VecDeque<Entry>,
+    capacity: usize,
+}
+
+static LEDGER: Mutex<Ledger> = Mutex::new(Ledger {
+    entries: VecDeque::new(),
+    capacity: DEFAULT_CAPACITY,
+});
+
+fn lock() -> std::sync::MutexGuard<'static, Ledger> {
+    LEDGER.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn record<T>(location: &'static Location<'static>, debug: Option<String>) {
+    let thread = std::thread::current();
+    let entry = Entry {
+        type_name: std::any::type_name::<T>(),
+        location,
+        thread: thread.id(),
+        thread_name: thread.name().map(|n| n.to_string()),
+        time: SystemTime::now(),
+        debug,
+    };
+
+    let mut ledger = lock();
+
+    if ledger.capacity == 0 {
+        return;
+    }
+
+    while ledger.entries.len() >= ledger.capacity {
+        ledger.entries.pop_front();
+    }
+
+    ledger.entries.push_back(entry);
+}
+
+/// Set how many of the most recent entries are kept. Older entries beyond the
+/// new capacity are discarded.
+pub fn set_capacity(capacity: usize) {
+    let mut ledger = lock();
+    ledger.capacity = capacity;
+
+    while ledger.entries.len() > capacity {
+        ledger.entries.pop_front();
+    }
+}
+
+/// Every entry currently in the ledger, oldest first.
+pub fn entries() -> Vec<Entry> {
+    lock().entries.iter().cloned().collect()
+}
+
+/// The entries that satisfy a predicate, oldest first.
+///
+/// The predicate runs on a copy of the ledger, so it may panic or record
+/// disposals of its own.
+pub fn find<F>(mut f: F) -> Vec<Entry>
+where
+    F: FnMut(&Entry) -> bool,
+{
+    entries().into_iter().filter(|e| f(e)).collect()
+}
+
+/// The entries recording a disposal of a `T`, oldest first.
+pub fn by_type<T: ?Sized>() -> Vec<Entry> {
+    let name = std::any::type_name::<T>();
+    find(|e| e.type_name == name)
+}
+
+/// Forget every entry.
+pub fn clear() {
+    lock().entries.clear();
+}
+
+/// The ledger as text, one entry per line, oldest first.
+pub fn dump() -> String {
+    render(&lock())
+}
+
+fn render(ledger: &Ledger) -> String {
+    let mut out = String::new();
+
+    for entry in ledger.entries.iter() {
+        let _ = writeln!(out, "{}", entry);
+    }
+
+    out
+}
+
+/// Print the ledger to stderr whenever a thread panics, after running the
+/// previously installed panic hook.
+///
+/// ```
+/// use disown::ledger::{self, DisownRecorded};
+///
+/// ledger::install_panic_hook();
+/// 7u32.disown_recorded();
+///
+/// let found = std::panic::catch_unwind(|| ledger::find(|_| panic!("bad predicate")));
+/// assert!(found.is_err());
+/// ```
+pub fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+
+        // The panic may have struck while this very thread held the ledger.
+        match LEDGER.try_lock() {
+            Ok(ledger) => eprintln!("disown ledger:\n{}", render(&ledger)),
+            Err(TryLockError::Poisoned(e)) => {
+                eprintln!("disown ledger:\n{}", render(&e.into_inner()))
+            }
+            Err(TryLockError::WouldBlock) => eprintln!("disown ledger: busy, not printed"),
+        }
+    }));
+}
+
+/// Consume ownership, recording who did it.
+pub trait DisownRecorded: Sized {
+    /// Drop `self`, recording the disposal in the ledger.
+    #[track_caller]
+    fn disown_recorded(self) {
+        record::<Self>(Location::caller(), None);
+    }
+
+    /// Drop `self`, recording the disposal in the ledger along with its
+    /// `Debug` representation.
+    #[track_caller]
+    fn disown_recorded_debug(self)
+    where
+        Self: Debug,
+    {
+        record::<Self>(Location::caller(), Some(format!("{:?}", self)));
+    }
+}
+
+impl<T> DisownRecorded for T {}
@@ -35,6 +35,97 @@
 //! compile without opening a pair of `{}` and using a `;`, which doesn't look
 //! as nice.
 
+/// Invoke the given macro with every type that owns nothing and whose drop
+/// does nothing, so that the marker traits all agree on them.
+macro_rules! trivial_types {
+    ($m:ident) => {
+        $m!(
+            (),
+            bool,
+            char,
+            u8,
+            u16,
+            u32,
+            u64,
+            u128,
+            usize,
+            i8,
+            i16,
+            i32,
+            i64,
+            i128,
+            isize,
+            f32,
+            f64,
+            std::time::Duration,
+            std::time::Instant,
+            std::time::SystemTime,
+        );
+    };
+}
+
+pub mod async_close;
+pub mod background;
+pub mod bin;
+pub mod close;
+pub mod deep;
+pub mod exit;
+pub mod guard;
+pub mod hooks;
+pub mod ignore;
+pub mod incremental;
+pub mod leak;
+#[cfg(feature = "ledger")]
+pub mod ledger;
+pub mod must_disown;
+pub mod origin;
+#[cfg(all(unix, feature = "process"))]
+pub mod process;
+pub mod profile;
+mod reaper;
+pub mod recycle;
+#[cfg(all(unix, feature = "signal"))]
+pub mod signal;
+#[cfg(feature = "tokio")]
+pub mod task;
+pub mod teardown;
+pub mod testing;
+pub mod thread;
+pub mod unwind;
+pub mod void;
+pub mod zeroize;
+
+pub use async_close::AsyncDisown;
+pub use background::DisownInBackground;
+pub use bin::DisownInto;
+pub use close::Close;
+pub use deep::DeepDisown;
+#[cfg(feature = "derive")]
+pub use disown_derive::DeepDisown;
+#[cfg(feature = "derive")]
+pub use disown_derive::DisownZeroized;
+pub use exit::DisownAtExit;
+pub use guard::DisownGuard;
+pub use hooks::DisownHooked;
+pub use ignore::DisownErr;
+pub use incremental::DisownIncrementally;
+pub use leak::DisownOrLeak;
+#[cfg(feature = "ledger")]
+pub use ledger::DisownRecorded;
+pub use must_disown::MustDisown;
+#[cfg(all(unix, feature = "process"))]
+pub use process::{DetachCommand, DisownProcess};
+pub use profile::DisownTimed;
+pub use recycle::DisownTo;
+#[cfg(all(unix, feature = "signal"))]
+pub use signal::DisownOnSignal;
+#[cfg(feature = "tokio")]
+pub use task::DisownTask;
+pub use thread::DisownThread;
+pub use unwind::DisownCatchUnwind;
+pub use void::Void;
+pub use zeroize::DisownZeroized;
+
 /// Consume ownership in style.
 ///
 /// Unlike [`std::ops::Drop`], this is implemented for all `T`.
@@ -0,0 +1,281 @@
+//! Values that must be released on purpose.
+//!
+//! [`Disown::disown`](crate::Disown::disown) is an explicit `drop`.
+//! [`MustDisown`] offers the reverse guarantee: it complains when the value
+//! it wraps is dropped *implicitly*, say at the end of a scope or by an early
+//! `?` return. Transactions, leases and the like can be wrapped so that
+//! forgetting to commit or release them is caught.
+//!
+//! ```
+//! use disown::MustDisown;
+//!
+//! struct Lease(u32);
+//!
+//! let lease = MustDisown::new(Lease(7));
+//! assert_eq!(7, lease.0);
+//!
+//! // Any of `disown`, `into_inner` or `close_with` releases it quietly.
+//! lease.disown();
+//! ```
+//!
+//! What happens on an implicit drop is decided by a [`Policy`]. By default it
+//! is [`Policy::Panic`] in debug builds and [`Policy::Log`] in release builds,
+//! and it can be overridden per type with [`set_policy`].
+//!
+//! Test suites can use [`capture`] to collect implicit drops instead of
+//! panicking, and then assert that nothing slipped through.
+//!
+//! ```
+//! use disown::must_disown::{self, MustDisown};
+//!
+//! let ((), leaks) = must_disown::capture(|| {
+//!     let _forgotten = MustDisown::new(String::from("txn"));
+//! });
+//!
+//! assert_eq!(1, leaks.len());
+//! assert_eq!("alloc::string::String", leaks[0].type_name);
+//! ```
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::panic::Location;
+use std::sync::Mutex;
+
+use crate::DisownGuard;
+
+/// What to do when a [`MustDisown`] is dropped implicitly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Panic, unless the thread is already panicking, in which case log.
+    Panic,
+    /// Print a warning to stderr.
+    Log,
+    /// Drop the value silently, exactly like an unwrapped value.
+    Ignore,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        if cfg!(debug_assertions) {
+            Policy::Panic
+        } else {
+            Policy::Log
+        }
+    }
+}
+
+/// An implicit drop of a [`MustDisown`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Leak {
+    /// The name of the wrapped type, as given by [`std::any::type_name`].
+    pub type_name: &'static str,
+    /// Where the [`MustDisown`] was created.
+    pub location: &'static Location<'static>,
+}
+
+impl std::fmt::Display for Leak {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "MustDisown<{}> created at {} was dropped without being disowned",
+            self.type_name, self.location
+        )
+    }
+}
+
+/// Per-type overrides of the default [`Policy`], keyed by type name, since
+/// `MustDisown<T>` doesn't require `T: 'static`.
+static POLICIES: Mutex<Option<HashMap<&'static str, Policy>>> = Mutex::new(None);
+
+thread_local! {
+    /// Leaks collected by an active [`capture`] on this thread.
+    static CAPTURED: RefCell<Option<Vec<Leak>>> = const { RefCell::new(None) };
+}
+
+/// Set the [`Policy`] applied when a `MustDisown<T>` is dropped implicitly.
+///
+/// Policies are looked up by [`std::any::type_name`], which is not
+/// guaranteed to be unique: two distinct types with the same name share a
+/// policy. A `TypeId` would be exact, but would rule out wrapping types that
+/// borrow, such as a transaction holding `&mut Connection`.
+///
+/// ```
+/// use disown::must_disown::{self, MustDisown, Policy};
+///
+/// struct Scratch;
+///
+/// must_disown::set_policy::<Scratch>(Policy::Ignore);
+/// let _scratch = MustDisown::new(Scratch);
+/// ```
+pub fn set_policy<T: ?Sized>(policy: Policy) {
+    POLICIES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(HashMap::new)
+        .insert(std::any::type_name::<T>(), policy);
+}
+
+/// The [`Policy`] applied when a `MustDisown<T>` is dropped implicitly.
+pub fn policy<T: ?Sized>() -> Policy {
+    policy_by_name(std::any::type_name::<T>())
+}
+
+fn policy_by_name(name: &str) -> Policy {
+    POLICIES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .and_then(|m| m.get(name).copied())
+        .unwrap_or_default()
+}
+
+/// Run `f`, collecting every implicit [`MustDisown`] drop on the current
+/// thread instead of applying its [`Policy`].
+///
+/// Captures may be nested; each sees only the leaks from its own closure. If
+/// `f` panics, the capture ends and policies apply again as before.
+///
+/// ```
+/// use disown::must_disown::{self, MustDisown, Policy};
+///
+/// must_disown::set_policy::<u8>(Policy::Panic);
+///
+/// let _ = std::panic::catch_unwind(|| must_disown::capture(|| panic!("test failed")));
+/// let dropped = std::panic::catch_unwind(|| drop(MustDisown::new(1u8)));
+/// assert!(dropped.is_err());
+/// ```
+pub fn capture<F, R>(f: F) -> (R, Vec<Leak>)
+where
+    F: FnOnce() -> R,
+{
+    let outer = CAPTURED.with(|c| c.borrow_mut().replace(Vec::new()));
+
+    // If `f` panics, the outer capture must still be put back, or every later
+    // leak on this thread would vanish into an orphaned buffer.
+    let outer = DisownGuard::on_unwind(outer, |outer| {
+        CAPTURED.with(|c| *c.borrow_mut() = outer);
+    });
+
+    let result = f();
+    let outer = outer.dismiss();
+    let leaks = CAPTURED.with(|c| std::mem::replace(&mut *c.borrow_mut(), outer));
+
+    (result, leaks.unwrap_or_default())
+}
+
+/// A value that must be consumed explicitly.
+///
+/// Release it with [`MustDisown::disown`], [`MustDisown::into_inner`] or
+/// [`MustDisown::close_with`]. Dropping it any other way applies the
+/// [`Policy`] configured for `T`.
+pub struct MustDisown<T> {
+    value: Option<T>,
+    location: &'static Location<'static>,
+}
+
+impl<T> MustDisown<T> {
+    /// Wrap a value, remembering where this happened.
+    #[track_caller]
+    pub fn new(value: T) -> Self {
+        MustDisown {
+            value: Some(value),
+            location: Location::caller(),
+        }
+    }
+
+    /// Drop the wrapped value on purpose.
+    pub fn disown(self) {
+        drop(self.into_inner())
+    }
+
+    /// Unwrap the value, taking over responsibility for it.
+    pub fn into_inner(mut self) -> T {
+        // The value is only taken here and in `drop`, so it is always present.
+        self.value.take().expect("MustDisown value already taken")
+    }
+
+    /// Release the value through a named close method, such as a transaction's
+    /// `commit` or `rollback`.
+    ///
+    /// ```
+    /// use disown::MustDisown;
+    ///
+    /// struct Txn;
+    ///
+    /// impl Txn {
+    ///     fn commit(self) -> Result<(), ()> {
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let txn = MustDisown::new(Txn);
+    /// assert_eq!(Ok(()), txn.close_with(Txn::commit));
+    /// ```
+    pub fn close_with<F, R>(self, f: F) -> R
+    where
+        F: FnOnce(T) -> R,
+    {
+        f(self.into_inner())
+    }
+
+    /// Where this value was wrapped.
+    pub fn location(&self) -> &'static Location<'static> {
+        self.location
+    }
+}
+
+impl<T> Deref for MustDisown<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value.as_ref().expect("MustDisown value already taken")
+    }
+}
+
+impl<T> DerefMut for MustDisown<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value.as_mut().expect("MustDisown value already taken")
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for MustDisown<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("MustDisown").field(&self.value).finish()
+    }
+}
+
+impl<T> Drop for MustDisown<T> {
+    fn drop(&mut self) {
+        let value = match self.value.take() {
+            Some(value) => value,
+            None => return,
+        };
+
+        // Drop the inner value first, so that a panic below can't leak it.
+        drop(value);
+
+        let leak = Leak {
+            type_name: std::any::type_name::<T>(),
+            location: self.location,
+        };
+
+        let captured = CAPTURED.with(|c| match c.borrow_mut().as_mut() {
+            Some(leaks) => {
+                leaks.push(leak.clone());
+                true
+            }
+            None => false,
+        });
+
+        if captured {
+            return;
+        }
+
+        match policy_by_name(leak.type_name) {
+            Policy::Panic if !std::thread::panicking() => panic!("{}", leak),
+            Policy::Panic | Policy::Log => eprintln!("warning: {}", leak),
+            Policy::Ignore => {}
+        }
+    }
+}
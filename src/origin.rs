@@ -0,0 +1,316 @@
+//! Dropping values on the thread that created them.
+//!
+//! GUI and FFI handles often must be destroyed on the thread that created them,
+//! yet end up inside structures that are dropped elsewhere. A [`ThreadBound`]
+//! wrapper can be moved freely between threads, but its contents can only be
+//! touched on its origin thread. When it is dropped on any other thread, the
+//! value is sent back to the origin's disposal queue instead, to be dropped
+//! the next time that thread calls [`pump`].
+//!
+//! ```
+//! use disown::origin::{self, ThreadBound};
+//! use std::rc::Rc;
+//!
+//! let handle = ThreadBound::new(Rc::new("window"));
+//! assert_eq!("window", **handle.get().unwrap());
+//!
+//! std::thread::spawn(move || {
+//!     assert!(handle.get().is_err());
+//!     handle.disown_on_origin().unwrap();
+//! })
+//! .join()
+//! .unwrap();
+//!
+//! assert_eq!(1, origin::pending());
+//! assert_eq!(1, origin::pump());
+//! ```
+//!
+//! Dropping a `ThreadBound` implicitly works the same way: on its origin
+//! thread the value is dropped at once, and anywhere else it is queued.
+//!
+//! ```
+//! use disown::origin::{self, ThreadBound};
+//! use disown::testing::DropTracker;
+//!
+//! let tracker = DropTracker::new();
+//! let local = ThreadBound::new(tracker.token("local"));
+//! let remote = ThreadBound::new(tracker.token("remote"));
+//!
+//! drop(local);
+//! tracker.assert_dropped_once("local");
+//!
+//! std::thread::spawn(move || drop(remote)).join().unwrap();
+//! tracker.assert_alive("remote");
+//!
+//! assert_eq!(1, origin::pump());
+//! tracker.assert_dropped_once("remote");
+//! ```
+//!
+//! If the origin thread has already exited, the value can't be dropped
+//! anywhere safely, so it is leaked. [`ThreadBound::disown_on_origin`]
+//! reports this as an [`OriginExited`] error, while a plain drop prints a
+//! warning. Anything still queued when the origin thread exits is dropped
+//! during that thread's own teardown.
+
+use std::any::Any;
+use std::fmt;
+use std::mem::ManuallyDrop;
+use std::sync::{Arc, Mutex};
+use std::thread::ThreadId;
+
+/// A value that has come home to be dropped.
+struct Orphan(#[allow(dead_code)] Box<dyn Any>);
+
+// SAFETY: An `Orphan` is only ever created on a foreign thread by moving an
+// untouched value out of a `ThreadBound`, and is only dropped on its origin
+// thread. It is never accessed in between.
+unsafe impl Send for Orphan {}
+
+struct State {
+    alive: bool,
+    orphans: Vec<Orphan>,
+}
+
+/// One thread's disposal queue.
+struct Queue {
+    state: Mutex<State>,
+}
+
+impl Queue {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Closes the queue when its thread exits.
+struct Local(Arc<Queue>);
+
+impl Drop for Local {
+    fn drop(&mut self) {
+        let orphans = {
+            let mut state = self.0.lock();
+            state.alive = false;
+            std::mem::take(&mut state.orphans)
+        };
+
+        drop(orphans);
+    }
+}
+
+thread_local! {
+    static QUEUE: Local = Local(Arc::new(Queue {
+        state: Mutex::new(State {
+            alive: true,
+            orphans: Vec::new(),
+        }),
+    }));
+}
+
+/// Drop every value that has been sent back to the current thread, returning
+/// how many there were.
+pub fn pump() -> usize {
+    let orphans = QUEUE
+        .try_with(|q| std::mem::take(&mut q.0.lock().orphans))
+        .unwrap_or_default();
+    let n = orphans.len();
+
+    drop(orphans);
+    n
+}
+
+/// The number of values waiting for the current thread to [`pump`] them.
+pub fn pending() -> usize {
+    QUEUE
+        .try_with(|q| q.0.lock().orphans.len())
+        .unwrap_or_default()
+}
+
+/// The value's origin thread exited before it could be sent back, so it was
+/// leaked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginExited {
+    /// The thread that created the value.
+    pub origin: ThreadId,
+    /// The type of the leaked value.
+    pub type_name: &'static str,
+}
+
+impl fmt::Display for OriginExited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "origin thread {:?} of a ThreadBound<{}> has exited, so the value was leaked",
+            self.origin, self.type_name
+        )
+    }
+}
+
+impl std::error::Error for OriginExited {}
+
+/// The contents of a [`ThreadBound`] were accessed from the wrong thread.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrongThread {
+    /// The thread that created the value.
+    pub origin: ThreadId,
+    /// The thread that attempted the access.
+    pub current: ThreadId,
+}
+
+impl fmt::Display for WrongThread {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ThreadBound created on {:?} accessed from {:?}",
+            self.origin, self.current
+        )
+    }
+}
+
+impl std::error::Error for WrongThread {}
+
+/// A value that may only be used and dropped on the thread that created it.
+///
+/// The wrapper itself is [`Send`] and [`Sync`], even when `T` is not.
+pub struct ThreadBound<T: 'static> {
+    value: ManuallyDrop<T>,
+    origin: ThreadId,
+    queue: ManuallyDrop<Arc<Queue>>,
+}
+
+// SAFETY: The value is only ever accessed on its origin thread. Elsewhere it
+// is only moved, untouched, into the origin's queue.
+unsafe impl<T: 'static> Send for ThreadBound<T> {}
+
+// SAFETY: Shared access is checked against the origin thread, as above.
+unsafe impl<T: 'static> Sync for ThreadBound<T> {}
+
+impl<T: 'static> ThreadBound<T> {
+    /// Bind a value to the current thread.
+    pub fn new(value: T) -> Self {
+        ThreadBound {
+            value: ManuallyDrop::new(value),
+            origin: std::thread::current().id(),
+            queue: ManuallyDrop::new(QUEUE.with(|q| q.0.clone())),
+        }
+    }
+
+    /// The thread that created this value.
+    pub fn origin(&self) -> ThreadId {
+        self.origin
+    }
+
+    /// Is the current thread the origin thread?
+    pub fn is_origin(&self) -> bool {
+        std::thread::current().id() == self.origin
+    }
+
+    /// The value, if this is the origin thread.
+    pub fn get(&self) -> Result<&T, WrongThread> {
+        self.check()?;
+        Ok(&self.value)
+    }
+
+    /// The value, if this is the origin thread.
+    pub fn get_mut(&mut self) -> Result<&mut T, WrongThread> {
+        self.check()?;
+        Ok(&mut self.value)
+    }
+
+    /// Unwrap the value, if this is the origin thread.
+    pub fn into_inner(self) -> Result<T, Self> {
+        if !self.is_origin() {
+            return Err(self);
+        }
+
+        let mut this = ManuallyDrop::new(self);
+        // SAFETY: `this` is never dropped, so the value is taken exactly once,
+        // and the queue handle is released by hand exactly once.
+        unsafe {
+            ManuallyDrop::drop(&mut this.queue);
+            Ok(ManuallyDrop::take(&mut this.value))
+        }
+    }
+
+    /// Drop the value now if this is the origin thread, or send it back to
+    /// the origin's disposal queue otherwise.
+    ///
+    /// ```
+    /// use disown::origin::ThreadBound;
+    ///
+    /// let orphan = std::thread::spawn(|| ThreadBound::new(vec![1, 2, 3]))
+    ///     .join()
+    ///     .unwrap();
+    ///
+    /// assert!(orphan.disown_on_origin().is_err());
+    /// ```
+    pub fn disown_on_origin(mut self) -> Result<(), OriginExited> {
+        let result = self.release();
+        std::mem::forget(self);
+        result
+    }
+
+    fn check(&self) -> Result<(), WrongThread> {
+        let current = std::thread::current().id();
+
+        if current == self.origin {
+            Ok(())
+        } else {
+            Err(WrongThread {
+                origin: self.origin,
+                current,
+            })
+        }
+    }
+
+    /// Dispose of the value and the queue handle. Must be called exactly once.
+    fn release(&mut self) -> Result<(), OriginExited> {
+        // SAFETY: The caller guarantees that this happens only once, and that
+        // `self` is not used afterwards. Both fields are `ManuallyDrop`, so
+        // nothing else drops them again.
+        let (value, queue) = unsafe {
+            (
+                ManuallyDrop::take(&mut self.value),
+                ManuallyDrop::take(&mut self.queue),
+            )
+        };
+
+        if self.is_origin() {
+            drop(value);
+            return Ok(());
+        }
+
+        let mut state = queue.lock();
+
+        if state.alive {
+            state.orphans.push(Orphan(Box::new(value)));
+            Ok(())
+        } else {
+            std::mem::forget(value);
+            Err(OriginExited {
+                origin: self.origin,
+                type_name: std::any::type_name::<T>(),
+            })
+        }
+    }
+}
+
+impl<T: 'static> Drop for ThreadBound<T> {
+    fn drop(&mut self) {
+        if let Err(e) = self.release() {
+            eprintln!("warning: {}", e);
+        }
+    }
+}
+
+impl<T: fmt::Debug + 'static> fmt::Debug for ThreadBound<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("ThreadBound");
+
+        match self.get() {
+            Ok(value) => d.field("value", value),
+            Err(_) => d.field("value", &format_args!("<on another thread>")),
+        };
+
+        d.field("origin", &self.origin).finish()
+    }
+}
@@ -0,0 +1,188 @@
+//! Disowning child processes, like the shell's `disown` builtin.
+//!
+//! A [`Child`] that is simply dropped keeps running, but nobody ever waits on
+//! it, so it lingers as a zombie once it exits. It also still belongs to our
+//! session, and so dies with our terminal. This module handles both halves:
+//!
+//! - [`DetachCommand::detach`] configures a [`Command`] before it is spawned,
+//!   moving the child into its own session (`setsid`), optionally ignoring
+//!   `SIGHUP`, and pointing its stdio at `/dev/null` or at log files.
+//! - [`DisownProcess::disown_process`] hands a running [`Child`] to a
+//!   background reaper thread, which waits on it so that no zombies pile up.
+//!
+//! ```no_run
+//! use disown::process::{Detach, DetachCommand, DisownProcess};
+//! use std::process::Command;
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let pid = Command::new("my-helper")
+//!     .arg("--serve")
+//!     .detach(&Detach::new().log_to("/tmp/helper.log"))?
+//!     .spawn()?
+//!     .disown_process();
+//!
+//! println!("Helper running as {}", pid);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Requires the `process` feature, and is only available on Unix.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use crate::reaper::Reaper;
+
+/// How often the reaper checks on its children.
+const REAP_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How a detached child should be set up.
+#[derive(Debug, Clone)]
+pub struct Detach {
+    new_session: bool,
+    ignore_sighup: bool,
+    stdout: Option<PathBuf>,
+    stderr: Option<PathBuf>,
+}
+
+impl Default for Detach {
+    fn default() -> Self {
+        Detach {
+            new_session: true,
+            ignore_sighup: true,
+            stdout: None,
+            stderr: None,
+        }
+    }
+}
+
+impl Detach {
+    /// Start a new session, ignore `SIGHUP`, and discard all output.
+    pub fn new() -> Self {
+        Detach::default()
+    }
+
+    /// Whether to call `setsid` in the child, detaching it from our
+    /// controlling terminal. Defaults to `true`.
+    pub fn new_session(mut self, yes: bool) -> Self {
+        self.new_session = yes;
+        self
+    }
+
+    /// Whether the child should ignore `SIGHUP`. Defaults to `true`.
+    pub fn ignore_sighup(mut self, yes: bool) -> Self {
+        self.ignore_sighup = yes;
+        self
+    }
+
+    /// Append the child's stdout to the given file.
+    pub fn stdout(mut self, path: impl AsRef<Path>) -> Self {
+        self.stdout = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Append the child's stderr to the given file.
+    pub fn stderr(mut self, path: impl AsRef<Path>) -> Self {
+        self.stderr = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Append both stdout and stderr to the given file.
+    pub fn log_to(self, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        self.stdout(path).stderr(path)
+    }
+}
+
+fn output(path: &Option<PathBuf>) -> io::Result<Stdio> {
+    match path {
+        None => Ok(Stdio::null()),
+        Some(path) => append(path).map(Stdio::from),
+    }
+}
+
+fn append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Configure a [`Command`] so that the process it spawns is detached from ours.
+pub trait DetachCommand {
+    /// Apply the given [`Detach`] settings. Fails if a log file can't be
+    /// opened.
+    fn detach(&mut self, detach: &Detach) -> io::Result<&mut Self>;
+}
+
+impl DetachCommand for Command {
+    fn detach(&mut self, detach: &Detach) -> io::Result<&mut Self> {
+        self.stdin(Stdio::null());
+        self.stdout(output(&detach.stdout)?);
+        self.stderr(output(&detach.stderr)?);
+
+        let new_session = detach.new_session;
+        let ignore_sighup = detach.ignore_sighup;
+
+        // SAFETY: Only async-signal-safe functions are called between `fork`
+        // and `exec`.
+        unsafe {
+            self.pre_exec(move || {
+                if new_session && libc::setsid() == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                if ignore_sighup && libc::signal(libc::SIGHUP, libc::SIG_IGN) == libc::SIG_ERR {
+                    return Err(io::Error::last_os_error());
+                }
+
+                Ok(())
+            });
+        }
+
+        Ok(self)
+    }
+}
+
+/// Give up responsibility for a running process.
+///
+/// ```
+/// use disown::process::{Detach, DetachCommand, DisownProcess};
+/// use std::process::Command;
+///
+/// let child = Command::new("true").detach(&Detach::new()).unwrap().spawn().unwrap();
+/// let pid = child.id();
+///
+/// assert_eq!(pid, child.disown_process());
+/// ```
+pub trait DisownProcess {
+    /// Hand the process to the background reaper, returning its PID.
+    fn disown_process(self) -> u32;
+}
+
+impl DisownProcess for Child {
+    fn disown_process(self) -> u32 {
+        let pid = self.id();
+        reap(self);
+        pid
+    }
+}
+
+/// Waits on disowned children, so that they don't linger as zombies.
+static REAPER: Reaper<Child> = Reaper::new(
+    "disown-reaper",
+    REAP_INTERVAL,
+    |child| !matches!(child.try_wait(), Ok(None)),
+    drop,
+);
+
+fn reap(child: Child) {
+    if let Err((child, e)) = REAPER.watch(child) {
+        eprintln!(
+            "warning: failed to spawn the reaper thread, so process {} won't be waited on: {}",
+            child.id(),
+            e
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! Measuring how expensive values are to drop.
+//!
+//! [`DisownTimed::disown_timed`] is [`Disown::disown`](crate::Disown::disown)
+//! with a stopwatch. Each drop is recorded in a global registry under the
+//! dropped type's name and the call site, and the collected statistics can be
+//! dumped as text, as JSON, or in the Prometheus exposition format.
+//!
+//! ```
+//! use disown::DisownTimed;
+//!
+//! for n in 0..100 {
+//!     vec![0u8; n * 1024].disown_timed();
+//! }
+//!
+//! let stats = disown::profile::snapshot();
+//! let vecs = stats.iter().find(|s| s.type_name == "alloc::vec::Vec<u8>").unwrap();
+//! assert_eq!(100, vecs.count);
+//! assert!(vecs.p50 <= vecs.p99 && vecs.p99 <= vecs.max);
+//!
+//! println!("{}", disown::profile::to_text());
+//! ```
+//!
+//! Percentiles are estimated from power-of-two buckets, so they are accurate to
+//! within a factor of two. Counts, totals and maxima are exact.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::panic::Location;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// One bucket per power of two nanoseconds, which covers well over a century.
+const BUCKETS: usize = 64;
+
+/// The raw measurements for one type at one call site.
+#[derive(Clone)]
+struct Histogram {
+    count: u64,
+    total: Duration,
+    max: Duration,
+    buckets: [u64; BUCKETS],
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Histogram {
+            count: 0,
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+            buckets: [0; BUCKETS],
+        }
+    }
+
+    fn record(&mut self, elapsed: Duration) {
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        let bucket = (u64::BITS - nanos.leading_zeros()).saturating_sub(1) as usize;
+
+        self.count += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+        self.buckets[bucket] += 1;
+    }
+
+    /// The upper bound of the bucket containing the `q`th quantile.
+    fn quantile(&self, q: f64) -> Duration {
+        let rank = ((self.count as f64) * q).ceil().max(1.0) as u64;
+        let mut seen = 0;
+
+        for (i, n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                let upper = 1u64.checked_shl(i as u32 + 1).unwrap_or(u64::MAX);
+                return Duration::from_nanos(upper).min(self.max);
+            }
+        }
+
+        self.max
+    }
+}
+
+type Key = (&'static str, &'static Location<'static>);
+
+static REGISTRY: Mutex<Option<HashMap<Key, Histogram>>> = Mutex::new(None);
+
+/// Drop statistics for one type at one call site.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DropStats {
+    /// The dropped type, as given by [`std::any::type_name`].
+    pub type_name: &'static str,
+    /// Where `disown_timed` was called.
+    pub location: &'static Location<'static>,
+    /// How many values were dropped.
+    pub count: u64,
+    /// The time spent dropping all of them.
+    pub total: Duration,
+    /// The estimated median drop time.
+    pub p50: Duration,
+    /// The estimated 99th percentile drop time.
+    pub p99: Duration,
+    /// The longest drop time.
+    pub max: Duration,
+}
+
+/// Record a drop of a `T` at the given location.
+pub fn record<T: ?Sized>(location: &'static Location<'static>, elapsed: Duration) {
+    REGISTRY
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(HashMap::new)
+        .entry((std::any::type_name::<T>(), location))
+        .or_insert_with(Histogram::new)
+        .record(elapsed);
+}
+
+/// The statistics collected so far, most expensive call sites first.
+pub fn snapshot() -> Vec<DropStats> {
+    let registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    let mut stats: Vec<DropStats> = registry
+        .iter()
+        .flatten()
+        .map(|((type_name, location), h)| DropStats {
+            type_name,
+            location,
+            count: h.count,
+            total: h.total,
+            p50: h.quantile(0.5),
+            p99: h.quantile(0.99),
+            max: h.max,
+        })
+        .collect();
+
+    stats.sort_by(|a, b| {
+        b.total
+            .cmp(&a.total)
+            .then_with(|| a.type_name.cmp(b.type_name))
+            .then_with(|| a.location.to_string().cmp(&b.location.to_string()))
+    });
+
+    stats
+}
+
+/// Forget all statistics collected so far.
+pub fn reset() {
+    REGISTRY.lock().unwrap_or_else(|e| e.into_inner()).take();
+}
+
+/// The statistics as a human-readable table.
+pub fn to_text() -> String {
+    let mut out = String::new();
+
+    for s in snapshot() {
+        let _ = writeln!(
+            out,
+            "{} at {}: count={} total={:?} p50={:?} p99={:?} max={:?}",
+            s.type_name, s.location, s.count, s.total, s.p50, s.p99, s.max
+        );
+    }
+
+    out
+}
+
+/// The statistics as a JSON array of objects. Durations are in nanoseconds.
+pub fn to_json() -> String {
+    let entries: Vec<String> = snapshot()
+        .into_iter()
+        .map(|s| {
+            format!(
+                "{{\"type\":\"{}\",\"location\":\"{}\",\"count\":{},\"total_ns\":{},\"p50_ns\":{},\"p99_ns\":{},\"max_ns\":{}}}",
+                escape_json(s.type_name),
+                escape_json(&s.location.to_string()),
+                s.count,
+                s.total.as_nanos(),
+                s.p50.as_nanos(),
+                s.p99.as_nanos(),
+                s.max.as_nanos(),
+            )
+        })
+        .collect();
+
+    format!("[{}]", entries.join(","))
+}
+
+/// The statistics in the Prometheus text exposition format, as a summary named
+/// `disown_drop_seconds` plus a `disown_drop_seconds_max` gauge.
+///
+/// ```
+/// use disown::DisownTimed;
+///
+/// String::from("gone").disown_timed();
+///
+/// let metrics = disown::profile::to_prometheus();
+/// assert!(metrics.contains("disown_drop_seconds_count{type=\"alloc::string::String\""));
+/// ```
+pub fn to_prometheus() -> String {
+    let stats = snapshot();
+    let mut out = String::new();
+
+    out.push_str("# HELP disown_drop_seconds Time spent dropping values.\n");
+    out.push_str("# TYPE disown_drop_seconds summary\n");
+    for s in stats.iter() {
+        let labels = format!(
+            "type=\"{}\",location=\"{}\"",
+            escape_label(s.type_name),
+            escape_label(&s.location.to_string())
+        );
+        let _ = writeln!(
+            out,
+            "disown_drop_seconds{{{},quantile=\"0.5\"}} {}",
+            labels,
+            s.p50.as_secs_f64()
+        );
+        let _ = writeln!(
+            out,
+            "disown_drop_seconds{{{},quantile=\"0.99\"}} {}",
+            labels,
+            s.p99.as_secs_f64()
+        );
+        let _ = writeln!(
+            out,
+            "disown_drop_seconds_sum{{{}}} {}",
+            labels,
+            s.total.as_secs_f64()
+        );
+        let _ = writeln!(out, "disown_drop_seconds_count{{{}}} {}", labels, s.count);
+    }
+
+    out.push_str("# HELP disown_drop_seconds_max Longest time spent dropping a value.\n");
+    out.push_str("# TYPE disown_drop_seconds_max gauge\n");
+    for s in stats.iter() {
+        let _ = writeln!(
+            out,
+            "disown_drop_seconds_max{{type=\"{}\",location=\"{}\"}} {}",
+            escape_label(s.type_name),
+            escape_label(&s.location.to_string()),
+            s.max.as_secs_f64()
+        );
+    }
+
+    out
+}
+
+fn escape_json(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+fn escape_label(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Consume ownership, measuring how long it takes.
+pub trait DisownTimed {
+    /// Drop `self`, recording the time taken under its type and the caller's
+    /// location.
+    #[track_caller]
+    fn disown_timed(self);
+}
+
+impl<T> DisownTimed for T {
+    #[track_caller]
+    fn disown_timed(self) {
+        let location = Location::caller();
+        let start = Instant::now();
+        drop(self);
+        record::<T>(location, start.elapsed());
+    }
+}
@@ -0,0 +1,129 @@
+//! A background thread that polls values until they are done.
+//!
+//! Shared by the `process` and `thread` modules, which wait on children and
+//! join threads without blocking the caller.
+
+use std::panic::AssertUnwindSafe;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::unwind::PanicPayload;
+
+/// The longest a reaper waits between checks, however long its values run.
+const MAX_WAIT: Duration = Duration::from_secs(1);
+
+/// A reaper thread, started on first use and restarted if it ever dies.
+pub(crate) struct Reaper<W> {
+    name: &'static str,
+    interval: Duration,
+    is_done: fn(&mut W) -> bool,
+    finish: fn(W),
+    sender: Mutex<Option<Sender<W>>>,
+}
+
+impl<W: Send + 'static> Reaper<W> {
+    /// A reaper whose thread is called `name`, and which checks its values
+    /// with `is_done` every `interval`, passing each finished one to `finish`.
+    pub(crate) const fn new(
+        name: &'static str,
+        interval: Duration,
+        is_done: fn(&mut W) -> bool,
+        finish: fn(W),
+    ) -> Self {
+        Reaper {
+            name,
+            interval,
+            is_done,
+            finish,
+            sender: Mutex::new(None),
+        }
+    }
+
+    /// Hand a value to the reaper thread. If no thread can be started, the
+    /// value is handed back along with the reason.
+    pub(crate) fn watch(&self, value: W) -> Result<(), (W, std::io::Error)> {
+        let mut sender = self.sender.lock().unwrap_or_else(|e| e.into_inner());
+
+        let value = match sender.as_ref() {
+            None => value,
+            Some(s) => match s.send(value) {
+                Ok(()) => return Ok(()),
+                // The reaper died somehow. Start a fresh one.
+                Err(mpsc::SendError(value)) => value,
+            },
+        };
+
+        let (new_sender, receiver) = mpsc::channel();
+        let (interval, is_done, finish) = (self.interval, self.is_done, self.finish);
+        let spawned = std::thread::Builder::new()
+            .name(self.name.to_string())
+            .spawn(move || reaper_loop(receiver, interval, is_done, finish));
+
+        match spawned {
+            Ok(_) => {
+                // The receiver is alive in the new thread, so this can't fail.
+                let _ = new_sender.send(value);
+                *sender = Some(new_sender);
+                Ok(())
+            }
+            Err(e) => Err((value, e)),
+        }
+    }
+}
+
+fn reaper_loop<W>(
+    receiver: Receiver<W>,
+    interval: Duration,
+    is_done: fn(&mut W) -> bool,
+    finish: fn(W),
+) {
+    let mut values: Vec<W> = Vec::new();
+    let mut wait = interval;
+
+    loop {
+        let next = if values.is_empty() {
+            receiver.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            receiver.recv_timeout(wait)
+        };
+
+        match next {
+            Ok(value) => {
+                values.push(value);
+                wait = interval;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) if values.is_empty() => return,
+            Err(RecvTimeoutError::Disconnected) => std::thread::sleep(wait),
+        }
+
+        let mut running = Vec::with_capacity(values.len());
+        let mut finished = false;
+
+        for mut value in values {
+            if !is_done(&mut value) {
+                running.push(value);
+                continue;
+            }
+
+            finished = true;
+
+            // Nobody is waiting on the reaper to rethrow to, and unwinding
+            // out of here would lose every other value it is watching.
+            if let Err(payload) = std::panic::catch_unwind(AssertUnwindSafe(|| finish(value))) {
+                eprintln!("warning: {}", PanicPayload::new(payload));
+            }
+        }
+
+        values = running;
+
+        // Values that have been running for a while are likely to keep
+        // running, so check on them less and less often.
+        wait = if finished {
+            interval
+        } else {
+            (wait * 2).min(MAX_WAIT)
+        };
+    }
+}
@@ -0,0 +1,298 @@
+//! Returning values to a pool instead of dropping them.
+//!
+//! Buffers that are expensive to allocate can be handed back to a
+//! [`Recycler`] with [`DisownTo::disown_to`]. They are [`Reset`] to empty but
+//! keep their capacity, ready to be taken out again.
+//!
+//! ```
+//! use disown::recycle::{Builder, DisownTo, Recycler};
+//!
+//! let pool: Recycler<Vec<u8>> = Builder::new().max_pooled(8).build();
+//!
+//! let mut buf = pool.take();
+//! buf.extend_from_slice(b"Hello!");
+//! buf.disown_to(&pool);
+//!
+//! let buf = pool.take();
+//! assert!(buf.is_empty());
+//! assert!(buf.capacity() >= 6);
+//!
+//! let stats = pool.stats();
+//! assert_eq!((1, 1), (stats.hits, stats.misses));
+//! ```
+//!
+//! A `Recycler` is a cheap handle, and clones of it share the same pool, so it
+//! can be passed freely between threads. When the pool is full, or a value has
+//! grown beyond the configured capacity, it is simply dropped.
+
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Values that can be emptied for reuse.
+pub trait Reset {
+    /// Clear the contents, keeping any allocated capacity.
+    fn reset(&mut self);
+
+    /// How much capacity is being held onto, in elements. Used to enforce
+    /// [`Builder::max_capacity`].
+    fn capacity(&self) -> usize {
+        0
+    }
+}
+
+impl<T> Reset for Vec<T> {
+    fn reset(&mut self) {
+        self.clear()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl Reset for String {
+    fn reset(&mut self) {
+        self.clear()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T> Reset for VecDeque<T> {
+    fn reset(&mut self) {
+        self.clear()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: Ord> Reset for BinaryHeap<T> {
+    fn reset(&mut self) {
+        self.clear()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<K, V, S: BuildHasher> Reset for HashMap<K, V, S> {
+    fn reset(&mut self) {
+        self.clear()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T, S: BuildHasher> Reset for HashSet<T, S> {
+    fn reset(&mut self) {
+        self.clear()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+}
+
+/// Configuration for a [`Recycler`].
+#[derive(Debug, Clone)]
+pub struct Builder {
+    max_pooled: usize,
+    max_capacity: usize,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            max_pooled: 64,
+            max_capacity: usize::MAX,
+        }
+    }
+}
+
+impl Builder {
+    /// A `Builder` with the default limits.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// The most values the pool will hold at once. Defaults to 64.
+    pub fn max_pooled(mut self, max: usize) -> Self {
+        self.max_pooled = max;
+        self
+    }
+
+    /// Values whose [`Reset::capacity`] exceeds this are dropped rather than
+    /// pooled, so that one huge buffer doesn't pin its memory forever. No limit
+    /// by default.
+    pub fn max_capacity(mut self, max: usize) -> Self {
+        self.max_capacity = max;
+        self
+    }
+
+    /// Create the pool.
+    pub fn build<T>(self) -> Recycler<T> {
+        Recycler {
+            inner: Arc::new(Inner {
+                pool: Mutex::new(Vec::new()),
+                config: self,
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                recycled: AtomicU64::new(0),
+                discarded: AtomicU64::new(0),
+            }),
+        }
+    }
+}
+
+/// Counts of a [`Recycler`]'s activity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RecyclerStats {
+    /// Takes served from the pool.
+    pub hits: u64,
+    /// Takes that found the pool empty.
+    pub misses: u64,
+    /// Values returned to the pool.
+    pub recycled: u64,
+    /// Values dropped because the pool was full or they were too large.
+    pub discarded: u64,
+    /// Values currently in the pool.
+    pub pooled: usize,
+}
+
+struct Inner<T> {
+    pool: Mutex<Vec<T>>,
+    config: Builder,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    recycled: AtomicU64,
+    discarded: AtomicU64,
+}
+
+/// A pool of reusable values, shared across threads.
+pub struct Recycler<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Clone for Recycler<T> {
+    fn clone(&self) -> Self {
+        Recycler {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Default for Recycler<T> {
+    fn default() -> Self {
+        Builder::default().build()
+    }
+}
+
+impl<T> std::fmt::Debug for Recycler<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Recycler")
+            .field("config", &self.inner.config)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl<T> Recycler<T> {
+    /// A pool with the default limits.
+    pub fn new() -> Self {
+        Recycler::default()
+    }
+
+    /// Take a value from the pool, if there is one.
+    pub fn try_take(&self) -> Option<T> {
+        let value = self.lock().pop();
+
+        match value {
+            Some(_) => self.inner.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.inner.misses.fetch_add(1, Ordering::Relaxed),
+        };
+
+        value
+    }
+
+    /// Take a value from the pool, or create one if it's empty.
+    pub fn take_or_else<F>(&self, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        self.try_take().unwrap_or_else(f)
+    }
+
+    /// Take a value from the pool, or a default one if it's empty.
+    pub fn take(&self) -> T
+    where
+        T: Default,
+    {
+        self.take_or_else(T::default)
+    }
+
+    /// Counts of this pool's activity so far.
+    pub fn stats(&self) -> RecyclerStats {
+        RecyclerStats {
+            hits: self.inner.hits.load(Ordering::Relaxed),
+            misses: self.inner.misses.load(Ordering::Relaxed),
+            recycled: self.inner.recycled.load(Ordering::Relaxed),
+            discarded: self.inner.discarded.load(Ordering::Relaxed),
+            pooled: self.lock().len(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<T>> {
+        self.inner.pool.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T: Reset> Recycler<T> {
+    /// Reset a value and return it to the pool. If the pool is full or the
+    /// value is too large, it is dropped instead.
+    pub fn recycle(&self, mut value: T) {
+        if value.capacity() > self.inner.config.max_capacity {
+            self.inner.discarded.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        // Reset outside the lock, as it may be expensive.
+        value.reset();
+
+        let rejected = {
+            let mut pool = self.lock();
+            if pool.len() < self.inner.config.max_pooled {
+                pool.push(value);
+                None
+            } else {
+                Some(value)
+            }
+        };
+
+        match rejected {
+            None => self.inner.recycled.fetch_add(1, Ordering::Relaxed),
+            Some(_) => self.inner.discarded.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+}
+
+/// Consume ownership by returning a value to a [`Recycler`].
+pub trait DisownTo: Sized {
+    /// Reset `self` and hand it to the pool, or drop it if the pool won't
+    /// take it.
+    fn disown_to(self, recycler: &Recycler<Self>);
+}
+
+impl<T: Reset> DisownTo for T {
+    fn disown_to(self, recycler: &Recycler<Self>) {
+        recycler.recycle(self)
+    }
+}
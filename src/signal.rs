@@ -0,0 +1,399 @@
+//! Dropping values when the process is asked to stop.
+//!
+//! A process killed by `SIGTERM` never unwinds, so none of its `Drop`-based
+//! cleanup runs. Values given to [`disown_on_signal`] are dropped when one of
+//! the installed signals arrives instead, after which the process is
+//! terminated the way the signal normally would, so that its exit status is
+//! unchanged.
+//!
+//! ```no_run
+//! use disown::signal::{self, DisownOnSignal, Signal};
+//! use std::time::Duration;
+//!
+//! # fn main() -> std::io::Result<()> {
+//! # let log_sink = Vec::<u8>::new();
+//! signal::Builder::new()
+//!     .signals(&[Signal::Interrupt, Signal::Terminate])
+//!     .grace_period(Duration::from_secs(5))
+//!     .install()?;
+//!
+//! log_sink.disown_on_signal();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A signal handler may do almost nothing safely, so the handler installed
+//! here only writes the signal's number into a pipe. A dedicated thread reads
+//! it and drops the values on yet another thread, most recent first. Those
+//! registered with [`disown_at_exit`](crate::exit::disown_at_exit) are
+//! dropped afterwards. If that takes longer than the grace period, the
+//! process is terminated regardless. A second signal during disposal also
+//! terminates it immediately.
+//!
+//! Requires the `signal` feature, and is only available on Unix.
+
+use std::io;
+use std::os::raw::{c_int, c_void};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How long disposal may take by default before the process is terminated
+/// anyway.
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// A signal that can trigger disposal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Signal {
+    /// `SIGINT`, as sent by Ctrl-C.
+    Interrupt,
+    /// `SIGTERM`, as sent by `kill` and container runtimes.
+    Terminate,
+    /// `SIGHUP`, as sent when the controlling terminal closes.
+    Hangup,
+}
+
+impl Signal {
+    fn number(self) -> c_int {
+        match self {
+            Signal::Interrupt => libc::SIGINT,
+            Signal::Terminate => libc::SIGTERM,
+            Signal::Hangup => libc::SIGHUP,
+        }
+    }
+}
+
+/// Configuration for the signal handlers.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    signals: Vec<Signal>,
+    grace_period: Duration,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            signals: vec![Signal::Interrupt, Signal::Terminate, Signal::Hangup],
+            grace_period: DEFAULT_GRACE_PERIOD,
+        }
+    }
+}
+
+impl Builder {
+    /// A `Builder` for all three signals, with the default grace period.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// The signals that trigger disposal.
+    pub fn signals(mut self, signals: &[Signal]) -> Self {
+        self.signals = signals.to_vec();
+        self
+    }
+
+    /// How long disposal may take before the process is terminated anyway.
+    pub fn grace_period(mut self, grace_period: Duration) -> Self {
+        self.grace_period = grace_period;
+        self
+    }
+
+    /// Install the handlers. Installing again adds to the set of handled
+    /// signals, and replaces the grace period.
+    ///
+    /// ```
+    /// use disown::signal::{self, Signal};
+    /// use std::io::{BufRead, BufReader, Write};
+    /// use std::os::unix::process::ExitStatusExt;
+    /// use std::process::{Command, Stdio};
+    ///
+    /// struct Log;
+    ///
+    /// impl Drop for Log {
+    ///     fn drop(&mut self) {
+    ///         println!("flushed");
+    ///     }
+    /// }
+    ///
+    /// if std::env::var_os("DISOWN_SIGNAL_CHILD").is_some() {
+    ///     signal::Builder::new()
+    ///         .signals(&[Signal::Terminate])
+    ///         .install()
+    ///         .unwrap();
+    ///     signal::disown_on_signal(Log);
+    ///
+    ///     println!("ready");
+    ///     std::io::stdout().flush().unwrap();
+    ///     loop {
+    ///         std::thread::park();
+    ///     }
+    /// }
+    ///
+    /// let mut child = Command::new(std::env::current_exe().unwrap())
+    ///     .env("DISOWN_SIGNAL_CHILD", "1")
+    ///     .stdout(Stdio::piped())
+    ///     .spawn()
+    ///     .unwrap();
+    ///
+    /// let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    /// assert_eq!("ready", lines.next().unwrap().unwrap());
+    ///
+    /// let killed = Command::new("kill")
+    ///     .args(["-TERM", &child.id().to_string()])
+    ///     .status()
+    ///     .unwrap();
+    /// assert!(killed.success());
+    ///
+    /// assert_eq!("flushed", lines.next().unwrap().unwrap());
+    /// assert_eq!(Some(15), child.wait().unwrap().signal());
+    /// ```
+    pub fn install(self) -> io::Result<()> {
+        let mut config = config();
+        config.grace_period = self.grace_period;
+
+        if WRITE_FD.load(Ordering::SeqCst) < 0 {
+            let (read, write) = pipe()?;
+
+            std::thread::Builder::new()
+                .name("disown-signal".to_string())
+                .spawn(move || watch(read))?;
+
+            WRITE_FD.store(write, Ordering::SeqCst);
+        }
+
+        for signal in self.signals {
+            let number = signal.number();
+
+            // SAFETY: The action is fully initialized, and `handler` only does
+            // async-signal-safe work.
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = handler as extern "C" fn(c_int) as libc::sighandler_t;
+                action.sa_flags = libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+
+                if libc::sigaction(number, &action, std::ptr::null_mut()) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            if !config.installed.contains(&number) {
+                config.installed.push(number);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Install handlers for `SIGINT`, `SIGTERM` and `SIGHUP`, with the default
+/// grace period.
+pub fn install() -> io::Result<()> {
+    Builder::new().install()
+}
+
+struct Config {
+    installed: Vec<c_int>,
+    grace_period: Duration,
+}
+
+static CONFIG: Mutex<Config> = Mutex::new(Config {
+    installed: Vec::new(),
+    grace_period: DEFAULT_GRACE_PERIOD,
+});
+
+fn config() -> std::sync::MutexGuard<'static, Config> {
+    CONFIG.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+static VALUES: Mutex<Vec<Box<dyn Send>>> = Mutex::new(Vec::new());
+
+/// The write end of the pipe, once the watcher thread is running.
+static WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// Drop a value when one of the installed signals arrives.
+///
+/// Nothing happens until handlers are installed with [`install`] or a
+/// [`Builder`].
+pub fn disown_on_signal<T: Send + 'static>(value: T) {
+    VALUES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(Box::new(value));
+}
+
+/// The number of values waiting for a signal.
+pub fn pending() -> usize {
+    VALUES.lock().unwrap_or_else(|e| e.into_inner()).len()
+}
+
+/// Drop every value registered with [`disown_on_signal`] now, most recent
+/// first, returning how many there were.
+///
+/// ```
+/// use disown::signal;
+/// use disown::testing::DropTracker;
+///
+/// let tracker = DropTracker::new();
+/// signal::disown_on_signal(tracker.token("socket"));
+///
+/// assert_eq!(1, signal::run());
+/// tracker.assert_all_dropped();
+/// ```
+pub fn run() -> usize {
+    crate::exit::drain(&VALUES)
+}
+
+extern "C" fn handler(signal: c_int) {
+    let fd = WRITE_FD.load(Ordering::SeqCst);
+    let byte = signal as u8;
+    let errno = errno_location();
+
+    // SAFETY: `write` is async-signal-safe, and the pipe stays open for the
+    // life of the process. The interrupted code may be about to read `errno`,
+    // so it is put back as it was.
+    unsafe {
+        let saved = errno.map(|e| *e);
+
+        // If the pipe is full a signal is already pending, so losing this one
+        // is harmless. There is nothing else to be done about a failure here.
+        let _ = libc::write(fd, &byte as *const u8 as *const c_void, 1);
+
+        if let (Some(errno), Some(saved)) = (errno, saved) {
+            *errno = saved;
+        }
+    }
+}
+
+/// Where the calling thread's `errno` lives, on platforms where it is known.
+fn errno_location() -> Option<*mut c_int> {
+    // SAFETY: These only return a pointer to thread-local storage.
+    unsafe {
+        #[cfg(any(target_os = "linux", target_os = "emscripten", target_os = "redox"))]
+        return Some(libc::__errno_location());
+        #[cfg(any(target_os = "android", target_os = "netbsd", target_os = "openbsd"))]
+        return Some(libc::__errno());
+        #[cfg(any(
+            target_os = "macos",
+            target_os = "ios",
+            target_os = "freebsd",
+            target_os = "dragonfly"
+        ))]
+        return Some(libc::__error());
+        #[cfg(any(target_os = "solaris", target_os = "illumos"))]
+        return Some(libc::___errno());
+        #[allow(unreachable_code)]
+        None
+    }
+}
+
+/// A close-on-exec pipe whose write end never blocks.
+fn pipe() -> io::Result<(c_int, c_int)> {
+    let mut fds = [0; 2];
+
+    // SAFETY: `fds` has room for both ends, and each end is configured only
+    // once it exists.
+    unsafe {
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        for fd in fds {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+
+        let flags = libc::fcntl(fds[1], libc::F_GETFL);
+        libc::fcntl(fds[1], libc::F_SETFL, flags | libc::O_NONBLOCK);
+    }
+
+    Ok((fds[0], fds[1]))
+}
+
+fn watch(fd: c_int) {
+    loop {
+        let mut byte = 0u8;
+        // SAFETY: `byte` is a valid one-byte buffer.
+        let n = unsafe { libc::read(fd, &mut byte as *mut u8 as *mut c_void, 1) };
+
+        match n {
+            1 => terminate(c_int::from(byte)),
+            _ if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+            _ => return,
+        }
+    }
+}
+
+/// Dispose of everything within the grace period, then end the process as
+/// the signal would have.
+fn terminate(signal: c_int) {
+    let grace_period = {
+        let config = config();
+
+        // From here on, another signal ends the process at once.
+        for &number in config.installed.iter() {
+            restore(number);
+        }
+
+        config.grace_period
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    let spawned = std::thread::Builder::new()
+        .name("disown-signal-disposal".to_string())
+        .spawn(move || {
+            run();
+            crate::exit::run();
+            let _ = sender.send(());
+        });
+
+    match spawned {
+        Ok(_) => {
+            if let Err(mpsc::RecvTimeoutError::Timeout) = receiver.recv_timeout(grace_period) {
+                eprintln!(
+                    "warning: disposal exceeded its grace period of {:?}",
+                    grace_period
+                );
+            }
+        }
+        Err(_) => {
+            run();
+            crate::exit::run();
+        }
+    }
+
+    restore(signal);
+
+    // `raise` would signal only this thread, which may block the signal.
+    // SAFETY: Signalling a process has no memory safety requirements.
+    unsafe {
+        libc::kill(libc::getpid(), signal);
+    }
+
+    // If every thread blocks the signal, it stays pending. Exit with the
+    // status a shell would report for it instead.
+    std::thread::sleep(Duration::from_millis(100));
+    // SAFETY: Everything registered has already been disposed of.
+    unsafe {
+        libc::_exit(128 + signal);
+    }
+}
+
+/// Restore the default action for a signal.
+fn restore(signal: c_int) {
+    // SAFETY: Resetting a disposition has no memory safety requirements.
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+    }
+}
+
+/// Consume ownership, dropping the value when the process is signalled.
+pub trait DisownOnSignal {
+    /// Drop `self` when an installed signal arrives. See [`disown_on_signal`].
+    fn disown_on_signal(self);
+}
+
+impl<T: Send + 'static> DisownOnSignal for T {
+    fn disown_on_signal(self) {
+        disown_on_signal(self)
+    }
+}
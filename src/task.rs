@@ -0,0 +1,394 @@
+//! Fire-and-forget async tasks that still report their failures.
+//!
+//! Spawning a task and dropping its `JoinHandle` is the usual way to fire and
+//! forget in async code, but an error it returns or a panic that kills it then
+//! disappears without a trace. [`DisownTask::disown_task`] spawns the task
+//! into a [`Supervisor`] instead, which logs failures and panics, keeps
+//! count of them, and can wait for its tasks to finish at shutdown.
+//!
+//! ```
+//! use disown::task::{DisownTask, Supervisor};
+//! use std::time::Duration;
+//!
+//! let runtime = tokio::runtime::Builder::new_current_thread()
+//!     .enable_time()
+//!     .build()
+//!     .unwrap();
+//!
+//! runtime.block_on(async {
+//!     let supervisor = Supervisor::builder().max_concurrent(2).build();
+//!
+//!     for n in 0..5 {
+//!         async move {
+//!             tokio::task::yield_now().await;
+//!             if n == 3 {
+//!                 Err(format!("job {} failed", n))
+//!             } else {
+//!                 Ok(())
+//!             }
+//!         }
+//!         .disown_task_in(&supervisor);
+//!     }
+//!
+//!     tokio::spawn(async { panic!("out of widgets") }).disown_task_in(&supervisor);
+//!
+//!     assert!(supervisor.drain(Duration::from_secs(5)).await);
+//!
+//!     let stats = supervisor.stats();
+//!     assert_eq!(6, stats.spawned);
+//!     assert_eq!(1, stats.failed);
+//!     assert_eq!(1, stats.panicked);
+//! });
+//! ```
+//!
+//! A task's output is inspected through [`TaskOutput`]: an `Err` counts as a
+//! failure and is logged with its `Display` form. When a tokio `JoinHandle` is
+//! disowned, a panic in its task is recognized as such, but an error it
+//! returns is not inspected; disown the future itself for that.
+//!
+//! Tasks must be disowned from within a tokio runtime, and [`drain`] needs the
+//! runtime's time driver.
+//!
+//! Requires the `tokio` feature.
+
+use std::any::Any;
+use std::fmt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::sync::{Notify, Semaphore};
+use tokio::task::JoinError;
+
+use crate::unwind::PanicPayload;
+
+/// Why a supervised task is considered to have failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Failure {
+    /// The task returned an error, shown here in its `Display` form.
+    Error(String),
+    /// The task panicked, with the given message if there was one.
+    Panic(Option<String>),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Error(e) => write!(f, "failed: {}", e),
+            Failure::Panic(Some(m)) => write!(f, "panicked: {}", m),
+            Failure::Panic(None) => write!(f, "panicked"),
+        }
+    }
+}
+
+/// The output of a task that can be supervised.
+pub trait TaskOutput {
+    /// How the task failed, if it did.
+    fn into_failure(self) -> Option<Failure>;
+}
+
+impl TaskOutput for () {
+    fn into_failure(self) -> Option<Failure> {
+        None
+    }
+}
+
+impl<T, E> TaskOutput for Result<T, E>
+where
+    E: fmt::Display + 'static,
+{
+    fn into_failure(self) -> Option<Failure> {
+        let e = self.err()?;
+        let message = e.to_string();
+        let any: Box<dyn Any> = Box::new(e);
+
+        match any.downcast::<JoinError>() {
+            Err(_) => Some(Failure::Error(message)),
+            Ok(e) if e.is_panic() => {
+                let payload = PanicPayload::new(e.into_panic());
+                Some(Failure::Panic(payload.message().map(|m| m.to_string())))
+            }
+            // The task was aborted on purpose.
+            Ok(e) if e.is_cancelled() => None,
+            Ok(_) => Some(Failure::Error(message)),
+        }
+    }
+}
+
+/// Counts of a [`Supervisor`]'s tasks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SupervisorStats {
+    /// Tasks disowned so far.
+    pub spawned: u64,
+    /// Tasks that have not yet finished, including those waiting to start.
+    pub active: usize,
+    /// Tasks that finished without failing.
+    pub completed: u64,
+    /// Tasks that returned an error.
+    pub failed: u64,
+    /// Tasks that panicked.
+    pub panicked: u64,
+}
+
+/// Configuration for a [`Supervisor`].
+#[derive(Debug, Clone)]
+pub struct Builder {
+    name: String,
+    max_concurrent: Option<usize>,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            name: "disown".to_string(),
+            max_concurrent: None,
+        }
+    }
+}
+
+impl Builder {
+    /// A `Builder` with the default settings.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// The name shown when a task's failure is logged.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Let at most `n` futures run at once. The rest wait their turn. By
+    /// default there is no limit.
+    ///
+    /// A disowned `JoinHandle` counts against the limit while it is awaited,
+    /// although its task is already running.
+    pub fn max_concurrent(mut self, n: usize) -> Self {
+        self.max_concurrent = Some(n.max(1));
+        self
+    }
+
+    /// Create the supervisor.
+    pub fn build(self) -> Supervisor {
+        Supervisor {
+            inner: Arc::new(Inner {
+                name: self.name,
+                limit: self.max_concurrent.map(|n| Arc::new(Semaphore::new(n))),
+                active: AtomicUsize::new(0),
+                idle: Notify::new(),
+                spawned: AtomicU64::new(0),
+                completed: AtomicU64::new(0),
+                failed: AtomicU64::new(0),
+                panicked: AtomicU64::new(0),
+            }),
+        }
+    }
+}
+
+struct Inner {
+    name: String,
+    limit: Option<Arc<Semaphore>>,
+    active: AtomicUsize,
+    idle: Notify,
+    spawned: AtomicU64,
+    completed: AtomicU64,
+    failed: AtomicU64,
+    panicked: AtomicU64,
+}
+
+/// A set of disowned tasks, watched over.
+///
+/// Cloning a `Supervisor` gives another handle to the same set.
+#[derive(Clone)]
+pub struct Supervisor {
+    inner: Arc<Inner>,
+}
+
+impl Supervisor {
+    /// A supervisor with the default settings.
+    pub fn new() -> Self {
+        Builder::new().build()
+    }
+
+    /// Configure a supervisor before creating it.
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
+    /// Spawn a future onto the current runtime, under this supervisor.
+    ///
+    /// # Panics
+    ///
+    /// When called outside of a tokio runtime.
+    pub fn spawn<F>(&self, future: F)
+    where
+        F: Future + Send + 'static,
+        F::Output: TaskOutput,
+    {
+        let ticket = Ticket::new(self.inner.clone());
+        let limit = self.inner.limit.clone();
+
+        tokio::spawn(async move {
+            let _permit = match limit {
+                Some(limit) => limit.acquire_owned().await.ok(),
+                None => None,
+            };
+
+            let failure = match CatchUnwind(Box::pin(future)).await {
+                Ok(output) => output.into_failure(),
+                Err(payload) => Some(Failure::Panic(payload.message().map(|m| m.to_string()))),
+            };
+
+            ticket.finish(failure);
+        });
+    }
+
+    /// The number of tasks that have not yet finished.
+    pub fn active(&self) -> usize {
+        self.inner.active.load(Ordering::SeqCst)
+    }
+
+    /// Counts of this supervisor's tasks.
+    pub fn stats(&self) -> SupervisorStats {
+        SupervisorStats {
+            spawned: self.inner.spawned.load(Ordering::SeqCst),
+            active: self.active(),
+            completed: self.inner.completed.load(Ordering::SeqCst),
+            failed: self.inner.failed.load(Ordering::SeqCst),
+            panicked: self.inner.panicked.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Wait until every task has finished, or until the timeout passes.
+    /// Returns `true` if they all finished.
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+
+        loop {
+            // Registered before the check, so that no wakeup is missed.
+            let idle = self.inner.idle.notified();
+
+            if self.active() == 0 {
+                return true;
+            }
+
+            if tokio::time::timeout_at(deadline, idle).await.is_err() {
+                return self.active() == 0;
+            }
+        }
+    }
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Supervisor::new()
+    }
+}
+
+impl fmt::Debug for Supervisor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Supervisor")
+            .field("name", &self.inner.name)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+/// Counts a task as active for as long as it lives, even if the runtime
+/// drops it before it finishes.
+struct Ticket {
+    inner: Arc<Inner>,
+}
+
+impl Ticket {
+    fn new(inner: Arc<Inner>) -> Self {
+        inner.spawned.fetch_add(1, Ordering::SeqCst);
+        inner.active.fetch_add(1, Ordering::SeqCst);
+        Ticket { inner }
+    }
+
+    fn finish(self, failure: Option<Failure>) {
+        let counter = match &failure {
+            None => &self.inner.completed,
+            Some(Failure::Error(_)) => &self.inner.failed,
+            Some(Failure::Panic(_)) => &self.inner.panicked,
+        };
+
+        counter.fetch_add(1, Ordering::SeqCst);
+
+        if let Some(failure) = failure {
+            eprintln!("warning: {} task {}", self.inner.name, failure);
+        }
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        if self.inner.active.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.inner.idle.notify_waiters();
+        }
+    }
+}
+
+/// Catches a panic raised while polling the inner future.
+struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, PanicPayload>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = self.0.as_mut();
+
+        match std::panic::catch_unwind(AssertUnwindSafe(|| inner.poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Err(payload) => Poll::Ready(Err(PanicPayload::new(payload))),
+        }
+    }
+}
+
+static GLOBAL: OnceLock<Supervisor> = OnceLock::new();
+
+/// The supervisor used by [`DisownTask::disown_task`].
+pub fn global() -> &'static Supervisor {
+    GLOBAL.get_or_init(Supervisor::new)
+}
+
+/// Replace the settings of the global supervisor. This only works before it
+/// is first used; otherwise the given supervisor is handed back.
+pub fn set_global(supervisor: Supervisor) -> Result<(), Supervisor> {
+    GLOBAL.set(supervisor)
+}
+
+/// Wait for the global supervisor's tasks to finish. See
+/// [`Supervisor::drain`].
+pub async fn drain(timeout: Duration) -> bool {
+    global().drain(timeout).await
+}
+
+/// Consume ownership of a task, without losing track of how it ends.
+pub trait DisownTask {
+    /// Spawn this task under the global supervisor.
+    fn disown_task(self);
+
+    /// Spawn this task under the given supervisor.
+    fn disown_task_in(self, supervisor: &Supervisor);
+}
+
+impl<F> DisownTask for F
+where
+    F: Future + Send + 'static,
+    F::Output: TaskOutput,
+{
+    fn disown_task(self) {
+        self.disown_task_in(global())
+    }
+
+    fn disown_task_in(self, supervisor: &Supervisor) {
+        supervisor.spawn(self)
+    }
+}
@@ -0,0 +1,415 @@
+//! Orderly shutdown of services that depend on one another.
+//!
+//! A daemon's parts often have to be dropped in a particular order: the
+//! database pool after the HTTP server, the caches after the workers. A
+//! [`Teardown`] holds named values along with "must outlive" edges between
+//! them, and [`Teardown::teardown`] disowns them in an order that respects
+//! every edge.
+//!
+//! ```
+//! use disown::teardown::{Outcome, Teardown};
+//! use std::time::Duration;
+//!
+//! # fn main() -> Result<(), disown::teardown::TeardownError> {
+//! let mut services = Teardown::new();
+//! services.register("db", vec!["connection"; 4])?;
+//! services.register("http", String::from("server"))?;
+//! services.register("cache", [0u8; 64])?;
+//! services.outlive("db", "http")?;
+//! services.outlive("cache", "http")?;
+//! services.step_timeout(Duration::from_secs(5));
+//!
+//! let report = services.teardown();
+//! assert_eq!("http", report.steps[0].name);
+//! assert!(report.steps.iter().all(|s| s.outcome == Outcome::Completed));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Adding an edge that would form a cycle is an error. Values without a
+//! constraint between them are torn down in the reverse of the order in which
+//! they were registered, like locals at the end of a scope.
+//!
+//! A step that exceeds its timeout is abandoned: it carries on in the
+//! background, and teardown moves on. Since that step may still be using the
+//! values that must outlive it, those are skipped and leaked rather than
+//! dropped, along with anything that must outlive them in turn. Panics are
+//! caught and reported rather than aborting the teardown.
+//!
+//! ```
+//! use disown::teardown::{Outcome, Teardown};
+//! use disown::testing::DropTracker;
+//! use std::time::Duration;
+//!
+//! struct SlowServer;
+//!
+//! impl Drop for SlowServer {
+//!     fn drop(&mut self) {
+//!         std::thread::sleep(Duration::from_millis(500));
+//!     }
+//! }
+//!
+//! let tracker = DropTracker::new();
+//! let mut services = Teardown::new();
+//! services.register("db", tracker.token("db")).unwrap();
+//! services.register("http", SlowServer).unwrap();
+//! services.outlive("db", "http").unwrap();
+//! services.step_timeout(Duration::from_millis(20));
+//!
+//! let report = services.teardown();
+//! assert_eq!(Outcome::TimedOut, report.steps[0].outcome);
+//! assert_eq!(Outcome::Skipped("http".to_string()), report.steps[1].outcome);
+//! tracker.assert_alive("db");
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use crate::DisownCatchUnwind;
+
+/// A problem with the registered values or their edges.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TeardownError {
+    /// A value with this name is already registered.
+    Duplicate(String),
+    /// No value with this name is registered.
+    Unknown(String),
+    /// The edge would form a cycle, shown here as a chain of names where each
+    /// must outlive the next.
+    Cycle(Vec<String>),
+}
+
+impl fmt::Display for TeardownError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TeardownError::Duplicate(name) => write!(f, "{:?} is already registered", name),
+            TeardownError::Unknown(name) => write!(f, "{:?} is not registered", name),
+            TeardownError::Cycle(names) => {
+                write!(f, "teardown cycle: {}", names.join(" must outlive "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for TeardownError {}
+
+/// How a single step of a teardown went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The value was dropped.
+    Completed,
+    /// The value's drop panicked, with the given message if there was one.
+    Panicked(Option<String>),
+    /// The step exceeded its timeout, and was left running in the background.
+    TimedOut,
+    /// The value had to outlive the named step, which timed out, so it was
+    /// leaked rather than dropped.
+    Skipped(String),
+}
+
+/// One value's part in a teardown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    /// The name the value was registered under.
+    pub name: String,
+    /// How long the step took, or the timeout if it was exceeded.
+    pub elapsed: Duration,
+    /// How the step went.
+    pub outcome: Outcome,
+}
+
+/// The result of [`Teardown::teardown`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TeardownReport {
+    /// Each step, in the order performed.
+    pub steps: Vec<Step>,
+    /// The time taken by the whole teardown.
+    pub total: Duration,
+}
+
+impl TeardownReport {
+    /// Did every step complete?
+    pub fn is_clean(&self) -> bool {
+        self.steps.iter().all(|s| s.outcome == Outcome::Completed)
+    }
+}
+
+struct Node {
+    name: String,
+    value: Option<Box<dyn Send>>,
+    /// The nodes that must be torn down after this one.
+    outlived_by: Vec<usize>,
+}
+
+/// Named values, torn down in dependency order.
+///
+/// A `Teardown` that is dropped without [`Teardown::teardown`] having been
+/// called performs its teardown anyway, discarding the report.
+#[derive(Default)]
+pub struct Teardown {
+    nodes: Vec<Node>,
+    index: HashMap<String, usize>,
+    timeout: Option<Duration>,
+}
+
+impl Teardown {
+    /// An empty registry.
+    pub fn new() -> Self {
+        Teardown::default()
+    }
+
+    /// Register a value under a unique name.
+    pub fn register<T>(&mut self, name: impl Into<String>, value: T) -> Result<(), TeardownError>
+    where
+        T: Send + 'static,
+    {
+        let name = name.into();
+
+        if self.index.contains_key(&name) {
+            return Err(TeardownError::Duplicate(name));
+        }
+
+        self.index.insert(name.clone(), self.nodes.len());
+        self.nodes.push(Node {
+            name,
+            value: Some(Box::new(value)),
+            outlived_by: Vec::new(),
+        });
+
+        Ok(())
+    }
+
+    /// Require that `outliver` is torn down only after `dependent`.
+    ///
+    /// ```
+    /// use disown::teardown::{Teardown, TeardownError};
+    ///
+    /// let mut services = Teardown::new();
+    /// services.register("db", ()).unwrap();
+    /// services.register("http", ()).unwrap();
+    /// services.outlive("db", "http").unwrap();
+    ///
+    /// let cycle = vec!["http".to_string(), "db".to_string(), "http".to_string()];
+    /// assert_eq!(Err(TeardownError::Cycle(cycle)), services.outlive("http", "db"));
+    /// assert_eq!(vec!["http", "db"], services.order());
+    /// ```
+    pub fn outlive(&mut self, outliver: &str, dependent: &str) -> Result<(), TeardownError> {
+        let a = self.find(outliver)?;
+        let b = self.find(dependent)?;
+
+        // The new edge closes a cycle if `a` must already be torn down before `b`.
+        if let Some(mut path) = self.path(a, b) {
+            path.push(b);
+            path.push(a);
+            let names = path
+                .iter()
+                .rev()
+                .map(|&i| self.nodes[i].name.clone())
+                .collect();
+            return Err(TeardownError::Cycle(names));
+        }
+
+        self.nodes[b].outlived_by.push(a);
+        Ok(())
+    }
+
+    /// Give up on any step that takes longer than this. By default, steps may
+    /// take as long as they need.
+    pub fn step_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// The names of the registered values, in the order they would be torn
+    /// down.
+    pub fn order(&self) -> Vec<&str> {
+        self.schedule()
+            .into_iter()
+            .map(|i| self.nodes[i].name.as_str())
+            .collect()
+    }
+
+    /// Disown every value, respecting all edges, and report on each step.
+    pub fn teardown(mut self) -> TeardownReport {
+        self.run()
+    }
+
+    fn run(&mut self) -> TeardownReport {
+        let start = Instant::now();
+        let timeout = self.timeout;
+
+        // For each node, the timed-out step that it has to outlive, if any.
+        let mut blocked: Vec<Option<String>> = vec![None; self.nodes.len()];
+        let mut steps = Vec::new();
+
+        for i in self.schedule() {
+            let node = &mut self.nodes[i];
+            let value = match node.value.take() {
+                Some(value) => value,
+                None => continue,
+            };
+
+            let step = match blocked[i].take() {
+                None => step(node.name.clone(), value, timeout),
+                Some(blocker) => {
+                    std::mem::forget(value);
+                    Step {
+                        name: node.name.clone(),
+                        elapsed: Duration::ZERO,
+                        outcome: Outcome::Skipped(blocker),
+                    }
+                }
+            };
+
+            let blocker = match &step.outcome {
+                Outcome::TimedOut => Some(step.name.clone()),
+                Outcome::Skipped(blocker) => Some(blocker.clone()),
+                _ => None,
+            };
+
+            if let Some(blocker) = blocker {
+                for &after in node.outlived_by.iter() {
+                    blocked[after].get_or_insert_with(|| blocker.clone());
+                }
+            }
+
+            steps.push(step);
+        }
+
+        TeardownReport {
+            steps,
+            total: start.elapsed(),
+        }
+    }
+
+    fn find(&self, name: &str) -> Result<usize, TeardownError> {
+        self.index
+            .get(name)
+            .copied()
+            .ok_or_else(|| TeardownError::Unknown(name.to_string()))
+    }
+
+    /// A path of outlived-by edges from one node to another, excluding the
+    /// destination.
+    fn path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        if from == to {
+            return Some(Vec::new());
+        }
+
+        let mut seen = vec![false; self.nodes.len()];
+        let mut stack = vec![(from, vec![from])];
+
+        while let Some((node, path)) = stack.pop() {
+            for &next in self.nodes[node].outlived_by.iter() {
+                if next == to {
+                    return Some(path);
+                }
+
+                if !seen[next] {
+                    seen[next] = true;
+                    let mut path = path.clone();
+                    path.push(next);
+                    stack.push((next, path));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// A topological order of the nodes, preferring the most recently
+    /// registered whenever there's a choice.
+    fn schedule(&self) -> Vec<usize> {
+        let mut blockers = vec![0; self.nodes.len()];
+        for node in self.nodes.iter() {
+            for &after in node.outlived_by.iter() {
+                blockers[after] += 1;
+            }
+        }
+
+        let mut ready: Vec<usize> = (0..self.nodes.len())
+            .filter(|&i| blockers[i] == 0)
+            .collect();
+        let mut order = Vec::with_capacity(self.nodes.len());
+
+        // `ready` is kept sorted, so the most recent node is at the end.
+        while let Some(i) = ready.pop() {
+            order.push(i);
+
+            for &after in self.nodes[i].outlived_by.iter() {
+                blockers[after] -= 1;
+                if blockers[after] == 0 {
+                    let at = ready.partition_point(|&r| r < after);
+                    ready.insert(at, after);
+                }
+            }
+        }
+
+        order
+    }
+}
+
+fn step(name: String, value: Box<dyn Send>, timeout: Option<Duration>) -> Step {
+    let start = Instant::now();
+
+    let outcome = match timeout {
+        None => outcome(value.disown_catch_unwind()),
+        Some(timeout) => {
+            // The value is only handed over once the thread is running, so
+            // that it can still be disowned here if the thread can't start.
+            let (value_sender, value_receiver) = mpsc::channel::<Box<dyn Send>>();
+            let (sender, receiver) = mpsc::channel();
+            let spawned = std::thread::Builder::new()
+                .name(format!("teardown-{}", name))
+                .spawn(move || {
+                    if let Ok(value) = value_receiver.recv() {
+                        let _ = sender.send(value.disown_catch_unwind());
+                    }
+                });
+
+            let handed_over = match spawned {
+                Ok(_) => value_sender.send(value),
+                Err(_) => Err(mpsc::SendError(value)),
+            };
+
+            match handed_over {
+                Err(mpsc::SendError(value)) => outcome(value.disown_catch_unwind()),
+                Ok(()) => match receiver.recv_timeout(timeout) {
+                    Ok(result) => outcome(result),
+                    Err(RecvTimeoutError::Timeout) => Outcome::TimedOut,
+                    Err(RecvTimeoutError::Disconnected) => Outcome::Panicked(None),
+                },
+            }
+        }
+    };
+
+    Step {
+        name,
+        elapsed: start.elapsed(),
+        outcome,
+    }
+}
+
+fn outcome(result: Result<(), crate::unwind::PanicPayload>) -> Outcome {
+    match result {
+        Ok(()) => Outcome::Completed,
+        Err(payload) => Outcome::Panicked(payload.message().map(|m| m.to_string())),
+    }
+}
+
+impl Drop for Teardown {
+    fn drop(&mut self) {
+        self.run();
+    }
+}
+
+impl fmt::Debug for Teardown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Teardown")
+            .field("order", &self.order())
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
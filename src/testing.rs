@@ -0,0 +1,299 @@
+//! Asserting that values are dropped exactly when they should be.
+//!
+//! A [`DropTracker`] hands out [`Token`]s, and records an event each time one
+//! is dropped. Put tokens in the container under test, and then check that
+//! each was dropped exactly once, in the expected order.
+//!
+//! ```
+//! use disown::testing::DropTracker;
+//!
+//! let tracker = DropTracker::new();
+//! let mut stack = vec![tracker.token("a"), tracker.token("b"), tracker.token("c")];
+//!
+//! stack.pop();
+//! tracker.assert_dropped_once("c");
+//! tracker.assert_alive("a");
+//!
+//! drop(stack);
+//! tracker.assert_drop_order(&["c", "a", "b"]);
+//! tracker.assert_all_dropped();
+//! ```
+//!
+//! Calling `.disown()` on a [`Token`] itself records the drop as explicit, so
+//! that tests can tell it apart from one that merely fell out of scope.
+//!
+//! ```
+//! use disown::testing::{DropKind, DropTracker};
+//!
+//! let tracker = DropTracker::new();
+//! let kept = tracker.token("kept");
+//! let released = tracker.token("released");
+//!
+//! released.disown();
+//! drop(kept);
+//!
+//! tracker.assert_dropped_as("released", DropKind::Explicit);
+//! tracker.assert_dropped_as("kept", DropKind::Implicit);
+//! ```
+//!
+//! Tokens don't point into their tracker's memory, so even a buggy container
+//! that drops the same token twice is recorded safely rather than causing
+//! further undefined behaviour.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// How a [`Token`] came to be dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DropKind {
+    /// Via [`Token::disown`].
+    Explicit,
+    /// By any other means, such as reaching the end of a scope.
+    Implicit,
+}
+
+/// A single drop of a [`Token`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DropEvent {
+    /// The order of this drop among all drops seen by the tracker, from 0.
+    pub seq: u64,
+    /// The label of the dropped token.
+    pub label: String,
+    /// How the token was dropped.
+    pub kind: DropKind,
+}
+
+#[derive(Default)]
+struct Log {
+    labels: Vec<String>,
+    events: Vec<DropEvent>,
+}
+
+/// Every live tracker's log, keyed by tracker ID.
+static LOGS: Mutex<Option<HashMap<u64, Log>>> = Mutex::new(None);
+
+static NEXT_TRACKER: AtomicU64 = AtomicU64::new(0);
+
+fn with_log<F, R>(tracker: u64, f: F) -> Option<R>
+where
+    F: FnOnce(&mut Log) -> R,
+{
+    LOGS.lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_mut()
+        .and_then(|logs| logs.get_mut(&tracker))
+        .map(f)
+}
+
+/// Hands out [`Token`]s and records when they are dropped.
+#[derive(Debug)]
+pub struct DropTracker {
+    id: u64,
+}
+
+impl Default for DropTracker {
+    fn default() -> Self {
+        DropTracker::new()
+    }
+}
+
+impl DropTracker {
+    /// A tracker with no tokens.
+    pub fn new() -> Self {
+        let id = NEXT_TRACKER.fetch_add(1, Ordering::Relaxed);
+
+        LOGS.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert_with(HashMap::new)
+            .insert(id, Log::default());
+
+        DropTracker { id }
+    }
+
+    /// Create a new token. Panics if the label is already taken.
+    #[track_caller]
+    pub fn token(&self, label: impl std::fmt::Display) -> Token {
+        let label = label.to_string();
+
+        let index = self.log(|log| {
+            assert!(
+                !log.labels.contains(&label),
+                "token {:?} already exists",
+                label
+            );
+            log.labels.push(label);
+            log.labels.len() - 1
+        });
+
+        Token {
+            tracker: self.id,
+            index,
+            kind: DropKind::Implicit,
+        }
+    }
+
+    /// Every drop recorded so far, in order.
+    pub fn events(&self) -> Vec<DropEvent> {
+        self.log(|log| log.events.clone())
+    }
+
+    /// How many times the token with this label has been dropped.
+    pub fn drop_count(&self, label: &str) -> usize {
+        self.log(|log| log.events.iter().filter(|e| e.label == label).count())
+    }
+
+    /// Assert that the token has been dropped exactly once.
+    #[track_caller]
+    pub fn assert_dropped_once(&self, label: &str) {
+        self.assert_exists(label);
+
+        let count = self.drop_count(label);
+        assert!(
+            count == 1,
+            "token {:?} was dropped {} times, expected once",
+            label,
+            count
+        );
+    }
+
+    /// Assert that the token was dropped exactly once, in the given way.
+    #[track_caller]
+    pub fn assert_dropped_as(&self, label: &str, kind: DropKind) {
+        self.assert_dropped_once(label);
+
+        let actual = self.log(|log| log.events.iter().find(|e| e.label == label).map(|e| e.kind));
+        assert!(
+            actual == Some(kind),
+            "token {:?} was dropped as {:?}, expected {:?}",
+            label,
+            actual,
+            kind
+        );
+    }
+
+    /// Assert that the token has not been dropped yet.
+    #[track_caller]
+    pub fn assert_alive(&self, label: &str) {
+        self.assert_exists(label);
+
+        let count = self.drop_count(label);
+        assert!(
+            count == 0,
+            "token {:?} was dropped {} times, expected it to be alive",
+            label,
+            count
+        );
+    }
+
+    /// Assert that the given tokens were dropped in exactly this order, and
+    /// that no others have been dropped.
+    #[track_caller]
+    pub fn assert_drop_order(&self, labels: &[&str]) {
+        let actual: Vec<String> = self.events().into_iter().map(|e| e.label).collect();
+        assert!(
+            actual == labels,
+            "tokens were dropped in the order {:?}, expected {:?}",
+            actual,
+            labels
+        );
+    }
+
+    /// Assert that every token handed out has been dropped exactly once.
+    #[track_caller]
+    pub fn assert_all_dropped(&self) {
+        let labels = self.log(|log| log.labels.clone());
+
+        for label in labels {
+            self.assert_dropped_once(&label);
+        }
+    }
+
+    #[track_caller]
+    fn assert_exists(&self, label: &str) {
+        let exists = self.log(|log| log.labels.iter().any(|l| l == label));
+        assert!(exists, "no token {:?} was created by this tracker", label);
+    }
+
+    fn log<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Log) -> R,
+    {
+        // The log lives for exactly as long as `self`.
+        with_log(self.id, f).expect("DropTracker log missing")
+    }
+}
+
+impl Drop for DropTracker {
+    fn drop(&mut self) {
+        if let Some(logs) = LOGS.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            logs.remove(&self.id);
+        }
+    }
+}
+
+/// An instrumented value whose drops are recorded by a [`DropTracker`].
+///
+/// Tokens compare, hash and order by their creation order, so they can be
+/// stored in any kind of collection.
+#[derive(Debug)]
+pub struct Token {
+    tracker: u64,
+    index: usize,
+    kind: DropKind,
+}
+
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        (self.tracker, self.index) == (other.tracker, other.index)
+    }
+}
+
+impl Eq for Token {}
+
+impl PartialOrd for Token {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Token {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.tracker, self.index).cmp(&(other.tracker, other.index))
+    }
+}
+
+impl std::hash::Hash for Token {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        (self.tracker, self.index).hash(state)
+    }
+}
+
+impl Token {
+    /// Drop this token on purpose, recording it as [`DropKind::Explicit`].
+    ///
+    /// This shadows [`Disown::disown`](crate::Disown::disown) when called on a
+    /// `Token` directly.
+    pub fn disown(mut self) {
+        self.kind = DropKind::Explicit;
+    }
+
+    /// The label this token was created with.
+    pub fn label(&self) -> String {
+        with_log(self.tracker, |log| log.labels[self.index].clone()).unwrap_or_default()
+    }
+}
+
+impl Drop for Token {
+    fn drop(&mut self) {
+        // Drops after the tracker itself is gone have nowhere to be recorded.
+        with_log(self.tracker, |log| {
+            let event = DropEvent {
+                seq: log.events.len() as u64,
+                label: log.labels[self.index].clone(),
+                kind: self.kind,
+            };
+            log.events.push(event);
+        });
+    }
+}
@@ -0,0 +1,273 @@
+//! Detaching threads without losing track of how they end.
+//!
+//! Dropping a [`JoinHandle`] detaches its thread, and whatever it returns, or
+//! the panic that killed it, is silently lost. [`DisownThread::disown_thread`]
+//! detaches the thread too, but a background reaper still collects its result
+//! and hands it to a [`Sink`]. By default, panics are printed to stderr.
+//!
+//! ```
+//! use disown::thread::{self, DisownThread, Sink};
+//! use std::sync::mpsc;
+//!
+//! let (sender, receiver) = mpsc::channel();
+//!
+//! std::thread::Builder::new()
+//!     .name("worker".to_string())
+//!     .spawn(|| -> u32 { panic!("out of widgets") })
+//!     .unwrap()
+//!     .disown_thread_to(Sink::Channel(sender));
+//!
+//! let exited = receiver.recv().unwrap();
+//! assert_eq!(Some("worker"), exited.name.as_deref());
+//! assert_eq!(Some("out of widgets"), exited.result.unwrap_err().message());
+//! assert!(thread::panics() >= 1);
+//! ```
+//!
+//! At shutdown, [`join_all_disowned`] waits for every disowned thread that is
+//! still running.
+
+use std::fmt;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::reaper::Reaper;
+use crate::unwind::{self, PanicPayload};
+
+/// How often the reaper checks on its threads.
+const REAP_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How a disowned thread ended.
+#[derive(Debug)]
+pub struct Exited<T> {
+    /// The thread's name, if it had one.
+    pub name: Option<String>,
+    /// What the thread returned, or the panic that ended it.
+    pub result: Result<T, PanicPayload>,
+}
+
+/// Where the outcome of a disowned thread is sent.
+pub enum Sink<T> {
+    /// Print panics to stderr, and discard successful results.
+    Log,
+    /// Discard everything. Panics are still tallied by [`panics`].
+    Count,
+    /// Call a function with the outcome, on the reaper thread.
+    ///
+    /// A panic in the function is handled by the
+    /// [`PanicPolicy`](crate::unwind::PanicPolicy), except that a rethrown
+    /// panic is only logged, since nothing is waiting on the reaper. Other
+    /// disowned threads are still looked after.
+    ///
+    /// ```
+    /// use disown::thread::{DisownThread, Sink};
+    /// use disown::unwind::{self, PanicPolicy};
+    /// use std::sync::mpsc;
+    /// use std::time::Duration;
+    ///
+    /// unwind::set_panic_policy(PanicPolicy::Rethrow);
+    /// let (sender, receiver) = mpsc::channel();
+    ///
+    /// std::thread::spawn(|| 1).disown_thread_to(Sink::callback(|_| panic!("oops")));
+    /// std::thread::spawn(|| {
+    ///     std::thread::sleep(Duration::from_millis(50));
+    ///     2
+    /// })
+    /// .disown_thread_to(Sink::Channel(sender));
+    ///
+    /// assert_eq!(2, receiver.recv().unwrap().result.unwrap());
+    /// ```
+    Callback(Box<dyn FnOnce(Exited<T>) + Send>),
+    /// Send the outcome down a channel.
+    Channel(Sender<Exited<T>>),
+}
+
+impl<T> Sink<T> {
+    /// A [`Sink::Callback`] that calls the given function.
+    pub fn callback<F>(f: F) -> Self
+    where
+        F: FnOnce(Exited<T>) + Send + 'static,
+    {
+        Sink::Callback(Box::new(f))
+    }
+}
+
+impl<T> fmt::Debug for Sink<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sink::Log => f.write_str("Log"),
+            Sink::Count => f.write_str("Count"),
+            Sink::Callback(_) => f.write_str("Callback(..)"),
+            Sink::Channel(sender) => f.debug_tuple("Channel").field(sender).finish(),
+        }
+    }
+}
+
+static PANICS: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of disowned threads that have panicked so far.
+pub fn panics() -> usize {
+    PANICS.load(Ordering::SeqCst)
+}
+
+static OUTSTANDING: Mutex<usize> = Mutex::new(0);
+static FINISHED: Condvar = Condvar::new();
+
+/// The number of disowned threads whose outcome hasn't been collected yet.
+pub fn outstanding() -> usize {
+    *OUTSTANDING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Wait until every disowned thread has finished and its outcome has been
+/// delivered, or until the timeout passes. Returns `true` if they all
+/// finished.
+///
+/// ```
+/// use disown::thread::{self, DisownThread, Sink};
+/// use std::time::Duration;
+///
+/// std::thread::spawn(|| std::thread::sleep(Duration::from_millis(20)))
+///     .disown_thread_to(Sink::Count);
+///
+/// assert!(thread::join_all_disowned(Duration::from_secs(5)));
+/// assert_eq!(0, thread::outstanding());
+/// ```
+pub fn join_all_disowned(timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    let mut outstanding = OUTSTANDING.lock().unwrap_or_else(|e| e.into_inner());
+
+    while *outstanding > 0 {
+        let left = deadline.saturating_duration_since(Instant::now());
+
+        if left.is_zero() {
+            return false;
+        }
+
+        outstanding = FINISHED
+            .wait_timeout(outstanding, left)
+            .unwrap_or_else(|e| e.into_inner())
+            .0;
+    }
+
+    true
+}
+
+/// Counts a disowned thread as outstanding for as long as it lives.
+struct Ticket;
+
+impl Ticket {
+    fn new() -> Self {
+        *OUTSTANDING.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+        Ticket
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let mut outstanding = OUTSTANDING.lock().unwrap_or_else(|e| e.into_inner());
+        *outstanding = outstanding.saturating_sub(1);
+        FINISHED.notify_all();
+    }
+}
+
+/// A disowned thread, as seen by the reaper.
+trait Watched: Send {
+    fn is_finished(&self) -> bool;
+
+    /// Join the thread, which must have finished, and deliver its outcome.
+    fn deliver(self: Box<Self>);
+}
+
+struct Disowned<T> {
+    handle: JoinHandle<T>,
+    sink: Sink<T>,
+    _ticket: Ticket,
+}
+
+impl<T: Send + 'static> Watched for Disowned<T> {
+    fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    fn deliver(self: Box<Self>) {
+        // The ticket is held until the outcome has been delivered.
+        let Disowned {
+            handle,
+            sink,
+            _ticket,
+        } = *self;
+
+        let name = handle.thread().name().map(|n| n.to_string());
+        let result = handle.join().map_err(PanicPayload::new);
+
+        if result.is_err() {
+            PANICS.fetch_add(1, Ordering::SeqCst);
+        }
+
+        match sink {
+            Sink::Log => {
+                if let Err(payload) = result {
+                    eprintln!(
+                        "warning: disowned thread {} panicked: {}",
+                        name.as_deref().unwrap_or("<unnamed>"),
+                        payload.message().unwrap_or("<non-string payload>"),
+                    );
+                }
+            }
+            Sink::Count => {}
+            Sink::Callback(f) => {
+                let exited = Exited { name, result };
+
+                if let Err(payload) = std::panic::catch_unwind(AssertUnwindSafe(|| f(exited))) {
+                    unwind::handle(PanicPayload::new(payload));
+                }
+            }
+            Sink::Channel(sender) => {
+                let _ = sender.send(Exited { name, result });
+            }
+        }
+    }
+}
+
+/// Joins disowned threads once they finish.
+static REAPER: Reaper<Box<dyn Watched>> = Reaper::new(
+    "disown-thread-reaper",
+    REAP_INTERVAL,
+    |watched| watched.is_finished(),
+    |watched| watched.deliver(),
+);
+
+fn reap(watched: Box<dyn Watched>) {
+    if let Err((_, e)) = REAPER.watch(watched) {
+        eprintln!(
+            "warning: failed to spawn the reaper thread, so a disowned thread's outcome will be lost: {}",
+            e
+        );
+    }
+}
+
+/// Consume ownership of a thread's handle, without losing its outcome.
+pub trait DisownThread<T> {
+    /// Detach the thread, printing a warning if it panics.
+    fn disown_thread(self);
+
+    /// Detach the thread, sending its outcome to `sink` once it finishes.
+    fn disown_thread_to(self, sink: Sink<T>);
+}
+
+impl<T: Send + 'static> DisownThread<T> for JoinHandle<T> {
+    fn disown_thread(self) {
+        self.disown_thread_to(Sink::Log)
+    }
+
+    fn disown_thread_to(self, sink: Sink<T>) {
+        reap(Box::new(Disowned {
+            handle: self,
+            sink,
+            _ticket: Ticket::new(),
+        }))
+    }
+}
@@ -0,0 +1,141 @@
+//! Containing panics that happen during disposal.
+//!
+//! A `Drop` impl that panics while the thread is already unwinding aborts the
+//! whole process. [`DisownCatchUnwind::disown_catch_unwind`] runs the drop
+//! inside [`std::panic::catch_unwind`] and returns any panic as an error.
+//!
+//! ```
+//! use disown::DisownCatchUnwind;
+//!
+//! struct Grumpy;
+//!
+//! impl Drop for Grumpy {
+//!     fn drop(&mut self) {
+//!         panic!("Not today!");
+//!     }
+//! }
+//!
+//! let err = Grumpy.disown_catch_unwind().unwrap_err();
+//! assert_eq!(Some("Not today!"), err.message());
+//! ```
+//!
+//! Panics caught during disposal that nobody is waiting on, such as on the
+//! [`background`](crate::background) drop thread, are handled according to a
+//! process-wide [`PanicPolicy`].
+//!
+//! Note that the panic hook still runs as usual, so by default the panic
+//! message is still printed to stderr.
+
+use std::any::Any;
+use std::fmt;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// The payload of a caught panic.
+pub struct PanicPayload(Box<dyn Any + Send + 'static>);
+
+impl PanicPayload {
+    /// Wrap a payload as returned by [`std::panic::catch_unwind`].
+    pub fn new(payload: Box<dyn Any + Send + 'static>) -> Self {
+        PanicPayload(payload)
+    }
+
+    /// The panic message, if the panic was raised with one.
+    pub fn message(&self) -> Option<&str> {
+        self.0
+            .downcast_ref::<&'static str>()
+            .copied()
+            .or_else(|| self.0.downcast_ref::<String>().map(|s| s.as_str()))
+    }
+
+    /// The raw payload.
+    pub fn into_inner(self) -> Box<dyn Any + Send + 'static> {
+        self.0
+    }
+
+    /// Continue unwinding with this payload.
+    pub fn resume(self) -> ! {
+        std::panic::resume_unwind(self.0)
+    }
+}
+
+impl fmt::Debug for PanicPayload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PanicPayload")
+            .field(&self.message().unwrap_or("<non-string payload>"))
+            .finish()
+    }
+}
+
+impl fmt::Display for PanicPayload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.message() {
+            Some(m) => write!(f, "panic during disposal: {}", m),
+            None => write!(f, "panic during disposal"),
+        }
+    }
+}
+
+impl std::error::Error for PanicPayload {}
+
+/// What to do with a panic caught during unattended disposal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PanicPolicy {
+    /// Print the panic to stderr and carry on.
+    #[default]
+    Log,
+    /// Abort the process.
+    Abort,
+    /// Resume unwinding on the thread that was doing the disposal.
+    Rethrow,
+}
+
+static POLICY: AtomicU8 = AtomicU8::new(0);
+
+/// Set how panics caught during unattended disposal are handled.
+pub fn set_panic_policy(policy: PanicPolicy) {
+    let n = match policy {
+        PanicPolicy::Log => 0,
+        PanicPolicy::Abort => 1,
+        PanicPolicy::Rethrow => 2,
+    };
+
+    POLICY.store(n, Ordering::Relaxed);
+}
+
+/// How panics caught during unattended disposal are handled.
+pub fn panic_policy() -> PanicPolicy {
+    match POLICY.load(Ordering::Relaxed) {
+        1 => PanicPolicy::Abort,
+        2 => PanicPolicy::Rethrow,
+        _ => PanicPolicy::Log,
+    }
+}
+
+/// Apply the current [`PanicPolicy`] to a caught panic.
+pub(crate) fn handle(payload: PanicPayload) {
+    match panic_policy() {
+        PanicPolicy::Log => eprintln!("{}", payload),
+        PanicPolicy::Abort => {
+            eprintln!("{}, aborting", payload);
+            std::process::abort()
+        }
+        PanicPolicy::Rethrow => payload.resume(),
+    }
+}
+
+/// Consume ownership, catching any panic raised by the value's destructor.
+pub trait DisownCatchUnwind {
+    /// Drop `self`, returning the payload of any panic as an error.
+    ///
+    /// As the value is gone afterwards, it isn't required to be
+    /// [`UnwindSafe`](std::panic::UnwindSafe). Shared state it referred to may
+    /// still have been left inconsistent by the panic, however.
+    fn disown_catch_unwind(self) -> Result<(), PanicPayload>;
+}
+
+impl<T> DisownCatchUnwind for T {
+    fn disown_catch_unwind(self) -> Result<(), PanicPayload> {
+        std::panic::catch_unwind(AssertUnwindSafe(move || drop(self))).map_err(PanicPayload)
+    }
+}
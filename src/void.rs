@@ -0,0 +1,175 @@
+//! Discarding the payload of a container, rather than the container itself.
+//!
+//! [`Disown::disown`](crate::Disown::disown) turns any `T` into `()`. Often
+//! though, only the *contents* are unwanted: a `Result<T, E>` should become a
+//! `Result<(), E>` so that it can still be `?`'d. [`Void`] does this for the
+//! standard containers, much like Haskell's `void`.
+//!
+//! ```
+//! use disown::Void;
+//! use std::collections::HashSet;
+//!
+//! fn remember(set: &mut HashSet<u32>, n: u32) -> Option<()> {
+//!     set.replace(n).void()
+//! }
+//!
+//! fn touch(path: &std::path::Path) -> std::io::Result<()> {
+//!     std::fs::OpenOptions::new().create(true).append(true).open(path).void()
+//! }
+//!
+//! let mut set = HashSet::new();
+//! assert_eq!(None, remember(&mut set, 1));
+//! assert_eq!(Some(()), remember(&mut set, 1));
+//! ```
+//!
+//! [`VoidFuture`] and [`VoidIterator`] do the same for the outputs of futures
+//! and the items of iterators.
+
+use std::future::Future;
+use std::iter::FusedIterator;
+use std::ops::ControlFlow;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Discard the payload of a container, keeping its shape.
+pub trait Void {
+    /// The container, with `()` in place of its payload.
+    type Output;
+
+    /// Replace the payload with `()`.
+    fn void(self) -> Self::Output;
+}
+
+impl<T, E> Void for Result<T, E> {
+    type Output = Result<(), E>;
+
+    fn void(self) -> Result<(), E> {
+        self.map(|_| ())
+    }
+}
+
+impl<T> Void for Option<T> {
+    type Output = Option<()>;
+
+    fn void(self) -> Option<()> {
+        self.map(|_| ())
+    }
+}
+
+impl<T> Void for Poll<T> {
+    type Output = Poll<()>;
+
+    fn void(self) -> Poll<()> {
+        self.map(|_| ())
+    }
+}
+
+/// Only the `Continue` value is discarded, as `?` carries the `Break` value.
+impl<B, C> Void for ControlFlow<B, C> {
+    type Output = ControlFlow<B, ()>;
+
+    fn void(self) -> ControlFlow<B, ()> {
+        match self {
+            ControlFlow::Continue(_) => ControlFlow::Continue(()),
+            ControlFlow::Break(b) => ControlFlow::Break(b),
+        }
+    }
+}
+
+/// Discard the output of a future.
+///
+/// ```
+/// use disown::void::VoidFuture;
+/// use std::future::Future;
+///
+/// fn needs_unit(_: impl Future<Output = ()>) {}
+///
+/// needs_unit(async { 5 }.void_output());
+/// ```
+pub trait VoidFuture: Future + Sized {
+    /// A future that resolves to `()` once `self` does.
+    fn void_output(self) -> VoidOutput<Self> {
+        VoidOutput { future: self }
+    }
+}
+
+impl<F: Future> VoidFuture for F {}
+
+/// The future returned by [`VoidFuture::void_output`].
+#[derive(Debug, Clone)]
+#[must_use = "futures do nothing unless polled"]
+pub struct VoidOutput<F> {
+    future: F,
+}
+
+impl<F> VoidOutput<F> {
+    /// Recover the original future.
+    pub fn into_inner(self) -> F {
+        self.future
+    }
+}
+
+impl<F: Future> Future for VoidOutput<F> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // SAFETY: `future` is structurally pinned. It is never moved out of a
+        // pinned `VoidOutput`, which has no `Drop` impl and is only `Unpin`
+        // when `F` is.
+        let future = unsafe { self.map_unchecked_mut(|v| &mut v.future) };
+        future.poll(cx).void()
+    }
+}
+
+/// Discard the items of an iterator.
+///
+/// ```
+/// use disown::void::VoidIterator;
+///
+/// let mut seen = Vec::new();
+/// let units: Vec<()> = (1..4).inspect(|n| seen.push(*n)).void_items().collect();
+///
+/// assert_eq!(vec![(), (), ()], units);
+/// assert_eq!(vec![1, 2, 3], seen);
+/// ```
+pub trait VoidIterator: Iterator + Sized {
+    /// An iterator that yields `()` for each item of `self`.
+    fn void_items(self) -> VoidItems<Self> {
+        VoidItems { iter: self }
+    }
+}
+
+impl<I: Iterator> VoidIterator for I {}
+
+/// The iterator returned by [`VoidIterator::void_items`].
+#[derive(Debug, Clone)]
+#[must_use = "iterators are lazy and do nothing unless consumed"]
+pub struct VoidItems<I> {
+    iter: I,
+}
+
+impl<I: Iterator> Iterator for VoidItems<I> {
+    type Item = ();
+
+    fn next(&mut self) -> Option<()> {
+        self.iter.next().void()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+
+    fn count(self) -> usize {
+        self.iter.count()
+    }
+}
+
+impl<I: DoubleEndedIterator> DoubleEndedIterator for VoidItems<I> {
+    fn next_back(&mut self) -> Option<()> {
+        self.iter.next_back().void()
+    }
+}
+
+impl<I: ExactSizeIterator> ExactSizeIterator for VoidItems<I> {}
+
+impl<I: FusedIterator> FusedIterator for VoidItems<I> {}
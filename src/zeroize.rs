@@ -0,0 +1,154 @@
+//! Wiping secrets before they are freed.
+//!
+//! Dropping a key or a password frees its memory, but leaves the bytes
+//! themselves behind for the next owner of that memory to find.
+//! [`DisownZeroized::disown_zeroized`] overwrites them with zeroes first, using
+//! volatile writes and a compiler fence so that the wipe can't be optimised
+//! away as a dead store.
+//!
+//! ```
+//! use disown::DisownZeroized;
+//!
+//! let mut password = String::with_capacity(64);
+//! password.push_str("hunter2");
+//!
+//! // All 64 bytes are wiped, not just the 7 in use.
+//! password.disown_zeroized();
+//! ```
+//!
+//! With the `derive` feature, `#[derive(DisownZeroized)]` wipes every field of
+//! a struct. Fields can be left alone with `#[disown_zeroized(skip)]`.
+//!
+//! ```
+//! # #[cfg(feature = "derive")]
+//! # {
+//! use disown::DisownZeroized;
+//!
+//! #[derive(DisownZeroized)]
+//! struct Credentials {
+//!     user: String,
+//!     key: [u8; 32],
+//!     #[disown_zeroized(skip)]
+//!     attempts: std::cell::Cell<u32>,
+//! }
+//!
+//! let mut creds = Credentials {
+//!     user: "root".to_string(),
+//!     key: [7; 32],
+//!     attempts: Default::default(),
+//! };
+//!
+//! // `key` lives inline, so it is wiped where it is rather than moved first.
+//! creds.wipe();
+//! drop(creds);
+//! # }
+//! ```
+//!
+//! Only memory owned by the value at the time of the wipe is covered. Copies
+//! left behind by earlier moves or reallocations are out of reach. That
+//! includes `disown_zeroized` itself: taking `self` by value moves the value,
+//! so secrets stored inline, like a `[u8; 32]` key, may stay behind in the
+//! caller's stack slot. Heap buffers aren't moved, so they are always wiped.
+//! For inline secrets, call [`DisownZeroized::wipe`] on the value where it
+//! lives, and only then drop it.
+
+use std::sync::atomic::{compiler_fence, Ordering};
+
+/// Consume ownership, wiping the value's memory first.
+pub trait DisownZeroized {
+    /// Overwrite the value's memory with zeroes, in place.
+    fn wipe(&mut self);
+
+    /// Wipe `self`, then drop it.
+    ///
+    /// This covers the heap memory `self` owns, but not any inline copy left
+    /// behind by moving `self` into this call. See the [module
+    /// docs](crate::zeroize) for values with inline secrets.
+    fn disown_zeroized(mut self)
+    where
+        Self: Sized,
+    {
+        self.wipe();
+    }
+}
+
+/// Volatile-write `len` zero bytes starting at `ptr`.
+///
+/// # Safety
+///
+/// `ptr` must be valid for writes of `len` bytes, and all-zeroes must be a
+/// valid bit pattern for whatever lives there.
+unsafe fn zero_bytes(ptr: *mut u8, len: usize) {
+    for i in 0..len {
+        std::ptr::write_volatile(ptr.add(i), 0);
+    }
+
+    compiler_fence(Ordering::SeqCst);
+}
+
+macro_rules! primitive {
+    ($($t:ty),* $(,)?) => {
+        $(
+            impl DisownZeroized for $t {
+                fn wipe(&mut self) {
+                    // SAFETY: `self` is a valid, exclusive reference to a
+                    // primitive for which zero is a valid value.
+                    unsafe { zero_bytes(self as *mut $t as *mut u8, std::mem::size_of::<$t>()) }
+                }
+            }
+
+            /// The entire capacity is wiped, not just the initialised part.
+            impl DisownZeroized for Vec<$t> {
+                fn wipe(&mut self) {
+                    let bytes = self.capacity() * std::mem::size_of::<$t>();
+                    // SAFETY: The allocation covers the full capacity, and
+                    // zero is a valid value for the element type.
+                    unsafe { zero_bytes(self.as_mut_ptr() as *mut u8, bytes) }
+                    self.clear();
+                }
+            }
+
+            impl DisownZeroized for Box<[$t]> {
+                fn wipe(&mut self) {
+                    let bytes = std::mem::size_of_val::<[$t]>(self);
+                    // SAFETY: As above, for a slice that owns its whole
+                    // allocation.
+                    unsafe { zero_bytes(self.as_mut_ptr() as *mut u8, bytes) }
+                }
+            }
+        )*
+    };
+}
+
+primitive!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, bool);
+
+/// The entire capacity is wiped, not just the initialised part.
+impl DisownZeroized for String {
+    fn wipe(&mut self) {
+        // SAFETY: Zero bytes are valid UTF-8, and the string is cleared
+        // afterwards in any case.
+        unsafe { self.as_mut_vec().wipe() }
+    }
+}
+
+/// Arrays are stored inline, so call `wipe` on them in place; see the [module
+/// docs](crate::zeroize).
+impl<T: DisownZeroized, const N: usize> DisownZeroized for [T; N] {
+    fn wipe(&mut self) {
+        self.iter_mut().for_each(DisownZeroized::wipe);
+    }
+}
+
+impl<T: DisownZeroized> DisownZeroized for Option<T> {
+    fn wipe(&mut self) {
+        if let Some(value) = self {
+            value.wipe();
+        }
+    }
+}
+
+impl<T: DisownZeroized + ?Sized> DisownZeroized for Box<T> {
+    fn wipe(&mut self) {
+        (**self).wipe();
+    }
+}